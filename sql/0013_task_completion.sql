@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS TaskCompletion (
+    task_id UUID NOT NULL REFERENCES Task(id) ON DELETE CASCADE,
+    account_id UUID NOT NULL REFERENCES Account(id) ON DELETE CASCADE,
+    completed_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+
+    CONSTRAINT taskcompletion_unique_connection UNIQUE (task_id, account_id)
+);
+
+CREATE INDEX taskcompletion_idx_account_id ON TaskCompletion(account_id);
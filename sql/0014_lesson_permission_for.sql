@@ -0,0 +1,5 @@
+-- Parameter names used to shadow the columns, matching every permission row
+CREATE OR REPLACE FUNCTION lesson_permission_for(lesson_id UUID, account_id UUID) RETURNS PermissionType AS $$
+    SELECT type FROM LessonPermission
+    WHERE LessonPermission.lesson_id = $1 AND LessonPermission.account_id = $2
+$$ LANGUAGE SQL STABLE;
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS LessonException (
+    original_date DATE NOT NULL,
+    -- NULL when the occurrence is cancelled
+    moved_to TIMESTAMP,
+    lesson_id UUID NOT NULL REFERENCES Lesson(id) ON DELETE CASCADE,
+
+    CONSTRAINT lessonexception_unique_date UNIQUE (lesson_id, original_date)
+);
+
+CREATE INDEX lessonexception_idx_moved_to ON LessonException(moved_to);
+
+CREATE OR REPLACE FUNCTION is_excepted_on(lesson UUID, target_date DATE) RETURNS BOOLEAN AS $$
+    SELECT EXISTS (SELECT FROM LessonException WHERE lesson_id = $1 AND original_date = $2)
+$$ LANGUAGE SQL STABLE;
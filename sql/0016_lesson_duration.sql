@@ -0,0 +1,21 @@
+-- Durations are stored in minutes. Existing rows get a regular 90 minute class
+ALTER TABLE SingleOccurrence
+    ADD COLUMN duration INTEGER NOT NULL DEFAULT 90,
+    ADD CONSTRAINT duration_is_positive CHECK (duration > 0);
+
+ALTER TABLE LessonDailyRepeat
+    ADD COLUMN duration INTEGER NOT NULL DEFAULT 90,
+    ADD CONSTRAINT duration_is_positive CHECK (duration > 0);
+
+ALTER TABLE LessonWeeklyRepeat
+    ADD COLUMN duration INTEGER NOT NULL DEFAULT 90,
+    ADD CONSTRAINT duration_is_positive CHECK (duration > 0);
+
+ALTER TABLE LessonMonthlyRepeat
+    ADD COLUMN duration INTEGER NOT NULL DEFAULT 90,
+    ADD CONSTRAINT duration_is_positive CHECK (duration > 0);
+
+-- Only meaningful for moved occurrences, defaults to 90 minutes when NULL
+ALTER TABLE LessonException
+    ADD COLUMN duration INTEGER,
+    ADD CONSTRAINT duration_is_positive CHECK (duration > 0);
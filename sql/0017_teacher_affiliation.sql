@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS TeacherAffiliationInvite (
+    teacher_id UUID NOT NULL REFERENCES Teacher(id) ON DELETE CASCADE,
+    account_id UUID NOT NULL REFERENCES Account(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+
+    CONSTRAINT teacheraffiliationinvite_unique_connection UNIQUE (teacher_id, account_id)
+);
+
+CREATE INDEX teacheraffiliationinvite_idx_account_id ON TeacherAffiliationInvite(account_id);
@@ -0,0 +1,42 @@
+-- New enum values can't be used in the transaction that adds them, so the type is recreated
+DROP FUNCTION is_read_permission;
+DROP FUNCTION lesson_permission_for;
+
+ALTER TYPE PermissionType RENAME TO PermissionTypeOld;
+CREATE TYPE PermissionType AS ENUM ('r', 'rw', 'owner');
+
+ALTER TABLE LessonPermission ALTER COLUMN type TYPE PermissionType USING type::TEXT::PermissionType;
+ALTER TABLE TeacherPermission ALTER COLUMN type TYPE PermissionType USING type::TEXT::PermissionType;
+ALTER TABLE TaskPermission ALTER COLUMN type TYPE PermissionType USING type::TEXT::PermissionType;
+
+DROP TYPE PermissionTypeOld;
+
+-- Before sharing, the only writer of an entity is the creator whose grant was written together
+-- with it. Sole writers become owners, entities with several writers have no known creator and
+-- keep them all as writers
+UPDATE LessonPermission SET type = 'owner'
+WHERE type = 'rw' AND lesson_id IN (
+    SELECT lesson_id FROM LessonPermission WHERE type = 'rw'
+    GROUP BY lesson_id HAVING count(*) = 1
+);
+
+UPDATE TeacherPermission SET type = 'owner'
+WHERE type = 'rw' AND teacher_id IN (
+    SELECT teacher_id FROM TeacherPermission WHERE type = 'rw'
+    GROUP BY teacher_id HAVING count(*) = 1
+);
+
+UPDATE TaskPermission SET type = 'owner'
+WHERE type = 'rw' AND task_id IN (
+    SELECT task_id FROM TaskPermission WHERE type = 'rw'
+    GROUP BY task_id HAVING count(*) = 1
+);
+
+CREATE OR REPLACE FUNCTION lesson_permission_for(lesson_id UUID, account_id UUID) RETURNS PermissionType AS $$
+    SELECT type FROM LessonPermission
+    WHERE LessonPermission.lesson_id = $1 AND LessonPermission.account_id = $2
+$$ LANGUAGE SQL STABLE;
+
+CREATE OR REPLACE FUNCTION is_read_permission(permission PermissionType) RETURNS BOOLEAN AS $$
+    SELECT permission IN ('r'::PermissionType, 'rw'::PermissionType, 'owner'::PermissionType)
+$$ LANGUAGE SQL STABLE;
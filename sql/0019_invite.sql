@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS Invite (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v1(),
+    issuer_id UUID NOT NULL REFERENCES Account(id) ON DELETE CASCADE,
+    max_uses INTEGER CHECK (max_uses > 0),
+    uses INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+CREATE INDEX invite_idx_issuer_id ON Invite(issuer_id);
@@ -0,0 +1,59 @@
+CREATE TABLE IF NOT EXISTS StudentGroup (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v1(),
+    name TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+-- Every account holding a permission on a group is its member
+CREATE TABLE IF NOT EXISTS GroupPermission (
+    type PermissionType NOT NULL,
+    group_id UUID NOT NULL REFERENCES StudentGroup(id) ON DELETE CASCADE,
+    account_id UUID NOT NULL REFERENCES Account(id) ON DELETE CASCADE,
+
+    CONSTRAINT grouppermission_unique_connection UNIQUE (group_id, account_id)
+);
+
+CREATE INDEX grouppermission_idx_account_id ON GroupPermission(account_id);
+
+CREATE TABLE IF NOT EXISTS LessonGroupPermission (
+    type PermissionType NOT NULL,
+    lesson_id UUID NOT NULL REFERENCES Lesson(id) ON DELETE CASCADE,
+    group_id UUID NOT NULL REFERENCES StudentGroup(id) ON DELETE CASCADE,
+
+    CONSTRAINT lessongrouppermission_unique_connection UNIQUE (lesson_id, group_id),
+    CONSTRAINT lessongrouppermission_not_owner CHECK (type <> 'owner')
+);
+
+CREATE INDEX lessongrouppermission_idx_group_id ON LessonGroupPermission(group_id);
+
+CREATE TABLE IF NOT EXISTS TeacherGroupPermission (
+    type PermissionType NOT NULL,
+    teacher_id UUID NOT NULL REFERENCES Teacher(id) ON DELETE CASCADE,
+    group_id UUID NOT NULL REFERENCES StudentGroup(id) ON DELETE CASCADE,
+
+    CONSTRAINT teachergrouppermission_unique_connection UNIQUE (teacher_id, group_id),
+    CONSTRAINT teachergrouppermission_not_owner CHECK (type <> 'owner')
+);
+
+CREATE INDEX teachergrouppermission_idx_group_id ON TeacherGroupPermission(group_id);
+
+-- Direct grants together with the ones given through group membership.
+-- An account may appear several times, the strongest permission is the effective one
+CREATE OR REPLACE VIEW EffectiveLessonPermission AS
+    SELECT type, lesson_id, account_id FROM LessonPermission
+    UNION ALL
+    SELECT LessonGroupPermission.type, lesson_id, GroupPermission.account_id
+    FROM LessonGroupPermission
+    JOIN GroupPermission ON LessonGroupPermission.group_id = GroupPermission.group_id;
+
+CREATE OR REPLACE VIEW EffectiveTeacherPermission AS
+    SELECT type, teacher_id, account_id FROM TeacherPermission
+    UNION ALL
+    SELECT TeacherGroupPermission.type, teacher_id, GroupPermission.account_id
+    FROM TeacherGroupPermission
+    JOIN GroupPermission ON TeacherGroupPermission.group_id = GroupPermission.group_id;
+
+CREATE OR REPLACE FUNCTION lesson_permission_for(lesson_id UUID, account_id UUID) RETURNS PermissionType AS $$
+    SELECT max(type) FROM EffectiveLessonPermission
+    WHERE EffectiveLessonPermission.lesson_id = $1 AND EffectiveLessonPermission.account_id = $2
+$$ LANGUAGE SQL STABLE;
@@ -0,0 +1,3 @@
+ALTER TABLE Account ADD COLUMN email TEXT;
+
+CREATE UNIQUE INDEX account_unique_email ON Account(lower(email));
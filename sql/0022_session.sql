@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS Session (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v1(),
+    account_id UUID NOT NULL REFERENCES Account(id) ON DELETE CASCADE,
+    token_id UUID NOT NULL,
+    user_agent TEXT,
+    ip TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    last_refreshed_at TIMESTAMP
+);
+
+CREATE INDEX session_idx_account_id ON Session(account_id);
+CREATE UNIQUE INDEX session_unique_token_id ON Session(token_id);
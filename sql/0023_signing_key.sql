@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS SigningKey (
+    kid TEXT PRIMARY KEY NOT NULL,
+    private_key TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    retired_at TIMESTAMP
+);
+
+-- Only one key signs new tokens
+CREATE UNIQUE INDEX signingkey_unique_current ON SigningKey((retired_at IS NULL)) WHERE retired_at IS NULL;
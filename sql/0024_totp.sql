@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS Totp (
+    account_id UUID PRIMARY KEY NOT NULL REFERENCES Account(id) ON DELETE CASCADE,
+    secret BYTEA NOT NULL,
+    confirmed_at TIMESTAMP,
+    last_used_step BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS RecoveryCode (
+    account_id UUID NOT NULL REFERENCES Account(id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    CONSTRAINT recoverycode_unique_code PRIMARY KEY (account_id, code_hash)
+);
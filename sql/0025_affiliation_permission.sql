@@ -0,0 +1,39 @@
+-- Affiliated accounts may write to their teacher and read the lessons it teaches for as long
+-- as the affiliation lasts, instead of being granted copies that outlive it
+CREATE OR REPLACE VIEW EffectiveLessonPermission AS
+    SELECT type, lesson_id, account_id FROM LessonPermission
+    UNION ALL
+    SELECT LessonGroupPermission.type, lesson_id, GroupPermission.account_id
+    FROM LessonGroupPermission
+    JOIN GroupPermission ON LessonGroupPermission.group_id = GroupPermission.group_id
+    UNION ALL
+    SELECT 'r'::PermissionType, TeacherLesson.lesson_id, Teacher.account_id
+    FROM TeacherLesson
+    JOIN Teacher ON TeacherLesson.teacher_id = Teacher.id
+    WHERE Teacher.account_id IS NOT NULL;
+
+CREATE OR REPLACE VIEW EffectiveTeacherPermission AS
+    SELECT type, teacher_id, account_id FROM TeacherPermission
+    UNION ALL
+    SELECT TeacherGroupPermission.type, teacher_id, GroupPermission.account_id
+    FROM TeacherGroupPermission
+    JOIN GroupPermission ON TeacherGroupPermission.group_id = GroupPermission.group_id
+    UNION ALL
+    SELECT 'rw'::PermissionType, id, account_id
+    FROM Teacher
+    WHERE account_id IS NOT NULL;
+
+-- Drop the copies written when affiliations were accepted and affiliated teachers were assigned,
+-- the views above grant the same access while the affiliation lasts
+DELETE FROM LessonPermission
+WHERE type = 'r' AND (lesson_id, account_id) IN (
+    SELECT TeacherLesson.lesson_id, Teacher.account_id
+    FROM TeacherLesson
+    JOIN Teacher ON TeacherLesson.teacher_id = Teacher.id
+    WHERE Teacher.account_id IS NOT NULL
+);
+
+DELETE FROM TeacherPermission
+WHERE type = 'rw' AND (teacher_id, account_id) IN (
+    SELECT id, account_id FROM Teacher WHERE account_id IS NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE Session ADD COLUMN IF NOT EXISTS previous_token_id UUID;
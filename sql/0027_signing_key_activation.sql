@@ -0,0 +1,4 @@
+ALTER TABLE SigningKey ADD COLUMN IF NOT EXISTS activates_at TIMESTAMP;
+UPDATE SigningKey SET activates_at = created_at WHERE activates_at IS NULL;
+ALTER TABLE SigningKey ALTER COLUMN activates_at SET DEFAULT (now() AT TIME ZONE 'utc');
+ALTER TABLE SigningKey ALTER COLUMN activates_at SET NOT NULL;
@@ -0,0 +1,3 @@
+-- Private keys are sealed with the key encryption key, plaintext ones are sealed on startup and cleared
+ALTER TABLE SigningKey ADD COLUMN IF NOT EXISTS sealed_private_key BYTEA;
+ALTER TABLE SigningKey ALTER COLUMN private_key DROP NOT NULL;
@@ -0,0 +1,3 @@
+-- Secrets are sealed with the key encryption key, plaintext ones are sealed on startup and cleared
+ALTER TABLE Totp ADD COLUMN IF NOT EXISTS sealed_secret BYTEA;
+ALTER TABLE Totp ALTER COLUMN secret DROP NOT NULL;
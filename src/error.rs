@@ -21,9 +21,15 @@ pub enum APIError {
 
     #[error("Login already present")]
     LoginAlreadyPresent,
+    #[error("Email already present")]
+    EmailAlreadyPresent,
 
     #[error("Invalid credentials")]
     InvalidCredentials,
+    #[error("Invalid one-time password")]
+    InvalidTotpCode,
+    #[error("Too many failed attempts, try again later")]
+    TooManyAttempts,
 
     #[error("Invalid token")]
     InvalidToken,
@@ -33,6 +39,8 @@ pub enum APIError {
     NoTokenPresent,
     #[error("Token revoked")]
     TokenRevoked,
+    #[error("Token already refreshed")]
+    TokenAlreadyRefreshed,
 
     #[error("Bad request")]
     BadRequest {
@@ -46,11 +54,25 @@ pub enum APIError {
 
     #[error("Lesson does not exist")]
     LessonDosNotExist,
+    #[error("Task does not exist")]
+    TaskDoesNotExist,
+    #[error("Account does not exist")]
+    AccountDoesNotExist,
+    #[error("Invitation does not exist")]
+    InvitationDoesNotExist,
+    #[error("Group does not exist")]
+    GroupDoesNotExist,
+    #[error("Session does not exist")]
+    SessionDoesNotExist,
 
     #[error("No read access")]
     NoReadAccess,
     #[error("No write access")]
     NoWriteAccess,
+    #[error("Not an owner")]
+    NotAnOwner,
+    #[error("Not an admin")]
+    NotAnAdmin,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -71,19 +93,30 @@ impl ResponseError for APIError {
     fn status_code(&self) -> StatusCode {
         match self {
             APIError::InternalError { message: _ } => StatusCode::INTERNAL_SERVER_ERROR,
-            APIError::LessonDosNotExist => StatusCode::NOT_FOUND,
+            APIError::LessonDosNotExist
+            | APIError::TaskDoesNotExist
+            | APIError::AccountDoesNotExist
+            | APIError::InvitationDoesNotExist
+            | APIError::GroupDoesNotExist
+            | APIError::SessionDoesNotExist => StatusCode::NOT_FOUND,
             APIError::InvalidCredentials
             | APIError::InvalidToken
             | APIError::TokenExpired
             | APIError::TokenRevoked
+            | APIError::InvalidTotpCode
             | APIError::NoTokenPresent
             | APIError::LoginAlreadyPresent => StatusCode::UNAUTHORIZED,
+            APIError::EmailAlreadyPresent | APIError::TokenAlreadyRefreshed => StatusCode::CONFLICT,
             APIError::BadRequest {
                 message: _,
                 scope: _,
             } => StatusCode::BAD_REQUEST,
-            APIError::NoReadAccess | APIError::NoWriteAccess => StatusCode::FORBIDDEN,
+            APIError::NoReadAccess
+            | APIError::NoWriteAccess
+            | APIError::NotAnOwner
+            | APIError::NotAnAdmin => StatusCode::FORBIDDEN,
             APIError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            APIError::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 
@@ -114,6 +147,14 @@ impl From<BcryptError> for APIError {
     }
 }
 
+impl From<openssl::error::ErrorStack> for APIError {
+    fn from(error: openssl::error::ErrorStack) -> Self {
+        APIError::InternalError {
+            message: format!("{}", error),
+        }
+    }
+}
+
 impl From<jwt::Error> for APIError {
     fn from(_: jwt::Error) -> Self {
         APIError::InvalidToken
@@ -0,0 +1,426 @@
+use chrono::{Duration, NaiveDateTime};
+use jwt::{
+    AlgorithmType, Header, PKeyWithDigest, SignWithKey, Token, Unverified, Verified, VerifyWithKey,
+};
+use openssl::{
+    error::ErrorStack,
+    hash::MessageDigest,
+    pkey::{Id, PKey, Private, Public},
+    rand::rand_bytes,
+    rsa::Rsa,
+    symm::{decrypt_aead, encrypt_aead, Cipher},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::env;
+use std::sync::RwLock;
+use thiserror::Error;
+
+use crate::error::APIError;
+use crate::model::{account::AccountID, signing_key::StoredKey, totp::Totp};
+use crate::token::{ApplicationToken, RefreshToken};
+
+#[derive(Error, Debug)]
+pub enum KeyringError {
+    #[error("Cannot read the signing key ({0})")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid signing key ({0})")]
+    OpenSSL(#[from] ErrorStack),
+    #[error("Unsupported signing key. Only RSA keys are supported")]
+    UnsupportedKey,
+    #[error("There is no current signing key")]
+    NoCurrentKey,
+    #[error("Cannot access stored signing keys ({0})")]
+    Database(#[from] sqlx::Error),
+    #[error("TOKEN_KEY_ENCRYPTION_KEY must be set to 32 base64 encoded bytes")]
+    InvalidEncryptionKey,
+    #[error("Cannot unseal {0}. Was it sealed with another encryption key?")]
+    Unsealable(String),
+}
+
+/// Public part of a signing key as published in the JWKS
+#[derive(Serialize, Clone, Debug)]
+pub struct Jwk {
+    pub kid: String,
+    pub kty: String,
+    #[serde(rename = "use")]
+    pub usage: String,
+    pub alg: AlgorithmType,
+    pub n: String,
+    pub e: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+fn encode(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+impl Jwk {
+    fn of(key: &PKey<Public>) -> Result<Jwk, KeyringError> {
+        // jwt has no EdDSA and drops leading zero bytes of ES256 signatures, so RSA it is
+        if key.id() != Id::RSA {
+            return Err(KeyringError::UnsupportedKey);
+        }
+        let rsa = key.rsa()?;
+        let n = encode(&rsa.n().to_vec());
+        let e = encode(&rsa.e().to_vec());
+
+        // RFC 7638 thumbprint serves as the key id
+        let canonical = format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, e, n);
+        Ok(Jwk {
+            kid: encode(&Sha256::digest(canonical.as_bytes())),
+            kty: "RSA".to_string(),
+            usage: "sig".to_string(),
+            alg: AlgorithmType::Rs256,
+            n,
+            e,
+        })
+    }
+}
+
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+/// Key private keys and TOTP secrets are sealed with before they are stored, they never reach the database in plaintext
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    pub fn from_env() -> Result<EncryptionKey, KeyringError> {
+        let encoded =
+            env::var("TOKEN_KEY_ENCRYPTION_KEY").map_err(|_| KeyringError::InvalidEncryptionKey)?;
+        Self::from_base64(&encoded)
+    }
+
+    pub fn from_base64(encoded: &str) -> Result<EncryptionKey, KeyringError> {
+        let decoded =
+            base64::decode(encoded.trim()).map_err(|_| KeyringError::InvalidEncryptionKey)?;
+        if decoded.len() != 32 {
+            return Err(KeyringError::InvalidEncryptionKey);
+        }
+        let mut key = [0; 32];
+        key.copy_from_slice(&decoded);
+        Ok(EncryptionKey(key))
+    }
+
+    /// AES-256-GCM with the name of the sealed value (the kid of a key) as associated data,
+    /// so a sealed value can't be passed off as another one.
+    /// The nonce, the ciphertext and the tag are concatenated in that order
+    pub fn seal(&self, kid: &str, plaintext: &[u8]) -> Result<Vec<u8>, KeyringError> {
+        let mut nonce = [0; NONCE_LENGTH];
+        rand_bytes(&mut nonce)?;
+        let mut tag = [0; TAG_LENGTH];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.0,
+            Some(&nonce),
+            kid.as_bytes(),
+            plaintext,
+            &mut tag,
+        )?;
+        Ok([&nonce[..], &ciphertext, &tag].concat())
+    }
+
+    pub fn unseal(&self, kid: &str, sealed: &[u8]) -> Result<Vec<u8>, KeyringError> {
+        if sealed.len() < NONCE_LENGTH + TAG_LENGTH {
+            return Err(KeyringError::Unsealable(kid.to_string()));
+        }
+        let (nonce, rest) = sealed.split_at(NONCE_LENGTH);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LENGTH);
+        decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.0,
+            Some(nonce),
+            kid.as_bytes(),
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| KeyringError::Unsealable(kid.to_string()))
+    }
+}
+
+pub struct SigningKey {
+    jwk: Jwk,
+    private: PKeyWithDigest<Private>,
+}
+
+impl From<KeyringError> for APIError {
+    fn from(error: KeyringError) -> Self {
+        APIError::InternalError {
+            message: format!("{}", error),
+        }
+    }
+}
+
+pub struct VerifyingKey {
+    jwk: Jwk,
+    public: PKeyWithDigest<Public>,
+}
+
+impl SigningKey {
+    pub fn from_pem(pem: &[u8]) -> Result<SigningKey, KeyringError> {
+        Self::new(PKey::private_key_from_pem(pem)?)
+    }
+
+    pub fn new(key: PKey<Private>) -> Result<SigningKey, KeyringError> {
+        let public = PKey::public_key_from_der(&key.public_key_to_der()?)?;
+        let jwk = Jwk::of(&public)?;
+        let private = PKeyWithDigest {
+            digest: MessageDigest::sha256(),
+            key,
+        };
+        Ok(SigningKey { jwk, private })
+    }
+
+    fn sealed(&self, encryption_key: &EncryptionKey) -> Result<Vec<u8>, KeyringError> {
+        encryption_key.seal(self.kid(), &self.private.key.private_key_to_pem_pkcs8()?)
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.jwk.kid
+    }
+
+    pub fn verifying_key(&self) -> Result<VerifyingKey, KeyringError> {
+        let public = PKey::public_key_from_der(&self.private.key.public_key_to_der()?)?;
+        Ok(VerifyingKey {
+            jwk: self.jwk.clone(),
+            public: PKeyWithDigest {
+                digest: MessageDigest::sha256(),
+                key: public,
+            },
+        })
+    }
+}
+
+struct KeySet {
+    current: SigningKey,
+    /// Key taking over signing at the given time
+    next: Option<(NaiveDateTime, SigningKey)>,
+    verifying: HashMap<String, VerifyingKey>,
+}
+
+/// Keys tokens are signed and verified with. Tokens name the key they were signed with in the `kid` header.
+/// New tokens are signed with the current key, retired keys verify tokens until the last of them expires
+pub struct Keyring {
+    keys: RwLock<KeySet>,
+    encryption_key: EncryptionKey,
+}
+
+/// How soon keys rotated by another instance are picked up
+pub const RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Rotated keys are published for two reload intervals before they sign anything,
+/// so every instance verifies the tokens signed with them
+fn activation_delay() -> Duration {
+    Duration::from_std(RELOAD_INTERVAL * 2).unwrap()
+}
+
+/// Retired keys are kept for as long as the longest living token signed with them
+pub fn retired_after() -> NaiveDateTime {
+    chrono::Utc::now().naive_utc() - RefreshToken::valid_for()
+}
+
+async fn load_keys(db: &PgPool, encryption_key: &EncryptionKey) -> Result<KeySet, KeyringError> {
+    let now = chrono::Utc::now().naive_utc();
+    let mut current = None;
+    let mut next = None;
+    let mut verifying = HashMap::new();
+    for stored in StoredKey::valid(db, &retired_after()).await? {
+        let key =
+            SigningKey::from_pem(&encryption_key.unseal(&stored.kid, &stored.sealed_private_key)?)?;
+        verifying.insert(key.kid().to_string(), key.verifying_key()?);
+        if stored.activates_at > now {
+            if stored.retired_at.is_none() {
+                next = Some((stored.activates_at, key));
+            }
+        } else if current.is_none() {
+            current = Some(key);
+        }
+    }
+
+    Ok(KeySet {
+        current: current.ok_or(KeyringError::NoCurrentKey)?,
+        next,
+        verifying,
+    })
+}
+
+/// Name TOTP secrets are sealed under, binding them to the account
+fn totp_secret_name(account_id: &AccountID) -> String {
+    format!("totp:{}", account_id)
+}
+
+fn generate_key() -> Result<PKey<Private>, KeyringError> {
+    Ok(PKey::from_rsa(Rsa::generate(2048)?)?)
+}
+
+impl Keyring {
+    /// Without a current key in the database, the one from the PEM file at `TOKEN_KEY_FILE` becomes current.
+    /// A key is generated if the file is not given either. Keys and TOTP secrets stored in plaintext are sealed
+    pub async fn from_env(db: &PgPool) -> Result<Keyring, KeyringError> {
+        let encryption_key = EncryptionKey::from_env()?;
+        for (kid, pem) in StoredKey::unsealed(db).await? {
+            StoredKey::seal(db, &kid, &encryption_key.seal(&kid, pem.as_bytes())?).await?;
+        }
+        for (account_id, secret) in Totp::unsealed(db).await? {
+            let sealed = encryption_key.seal(&totp_secret_name(&account_id), &secret)?;
+            Totp::seal(db, &account_id, &sealed).await?;
+        }
+
+        if let Err(KeyringError::NoCurrentKey) = load_keys(db, &encryption_key).await {
+            let key = match env::var("TOKEN_KEY_FILE")
+                .ok()
+                .filter(|path| !path.is_empty())
+            {
+                Some(path) => PKey::private_key_from_pem(&std::fs::read(path)?)?,
+                None => {
+                    log::warn!("TOKEN_KEY_FILE is not set, generating a signing key");
+                    generate_key()?
+                }
+            };
+            let key = SigningKey::new(key)?;
+            StoredKey::seed(db, key.kid(), &key.sealed(&encryption_key)?).await?;
+        }
+
+        Ok(Keyring {
+            keys: RwLock::new(load_keys(db, &encryption_key).await?),
+            encryption_key,
+        })
+    }
+
+    /// Picks up keys rotated by other instances
+    pub async fn reload(&self, db: &PgPool) -> Result<(), KeyringError> {
+        let keys = load_keys(db, &self.encryption_key).await?;
+        *self.keys.write().unwrap() = keys;
+        Ok(())
+    }
+
+    /// Publishes a freshly generated key which signs new tokens once every instance has loaded it.
+    /// Tokens signed with the previous one stay valid
+    pub async fn rotate(&self, db: &PgPool) -> Result<String, KeyringError> {
+        let key = SigningKey::new(generate_key()?)?;
+        let activates_at = chrono::Utc::now().naive_utc() + activation_delay();
+        StoredKey::rotate(
+            db,
+            key.kid(),
+            &key.sealed(&self.encryption_key)?,
+            &activates_at,
+        )
+        .await?;
+        StoredKey::purge(db, &retired_after()).await?;
+        self.reload(db).await?;
+        Ok(key.kid().to_string())
+    }
+
+    pub fn seal_totp_secret(
+        &self,
+        account_id: &AccountID,
+        secret: &[u8],
+    ) -> Result<Vec<u8>, KeyringError> {
+        self.encryption_key
+            .seal(&totp_secret_name(account_id), secret)
+    }
+
+    pub fn unseal_totp_secret(
+        &self,
+        account_id: &AccountID,
+        sealed_secret: &[u8],
+    ) -> Result<Vec<u8>, KeyringError> {
+        self.encryption_key
+            .unseal(&totp_secret_name(account_id), sealed_secret)
+    }
+
+    pub fn sign<C: Serialize>(&self, claims: &C) -> Result<String, jwt::Error> {
+        let keys = self.keys.read().unwrap();
+        let key = match &keys.next {
+            Some((activates_at, next)) if *activates_at <= chrono::Utc::now().naive_utc() => next,
+            _ => &keys.current,
+        };
+        let header = Header {
+            algorithm: key.jwk.alg,
+            key_id: Some(key.jwk.kid.clone()),
+            ..Header::default()
+        };
+        let token = Token::new(header, claims).sign_with_key(&key.private)?;
+        Ok(token.as_str().to_string())
+    }
+
+    pub fn verify<C: DeserializeOwned>(&self, token: &str) -> Result<C, APIError> {
+        let keys = self.keys.read().unwrap();
+        let token: Token<Header, C, Unverified> = Token::parse_unverified(token)?;
+        let key = token
+            .header()
+            .key_id
+            .as_ref()
+            .and_then(|kid| keys.verifying.get(kid))
+            .ok_or(APIError::InvalidToken)?;
+        let token: Token<Header, C, Verified> = token.verify_with_key(&key.public)?;
+        let (_, claims) = token.into();
+        Ok(claims)
+    }
+
+    pub fn jwks(&self) -> Jwks {
+        let keys = self.keys.read().unwrap();
+        Jwks {
+            keys: keys.verifying.values().map(|key| key.jwk.clone()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encryption_key() -> EncryptionKey {
+        EncryptionKey::from_base64(&base64::encode([7; 32])).unwrap()
+    }
+
+    #[test]
+    fn sealed_key_unseals_to_plaintext() {
+        let key = encryption_key();
+        let sealed = key.seal("kid", b"private key").unwrap();
+
+        assert!(!sealed.windows(11).any(|window| window == b"private key"));
+        assert_eq!(key.unseal("kid", &sealed).unwrap(), b"private key");
+    }
+
+    #[test]
+    fn sealed_key_is_bound_to_kid_and_encryption_key() {
+        let sealed = encryption_key().seal("kid", b"private key").unwrap();
+        let other = EncryptionKey::from_base64(&base64::encode([8; 32])).unwrap();
+
+        assert!(encryption_key().unseal("other kid", &sealed).is_err());
+        assert!(other.unseal("kid", &sealed).is_err());
+        assert!(encryption_key().unseal("kid", &sealed[1..]).is_err());
+    }
+
+    #[test]
+    fn totp_secret_is_bound_to_account() {
+        let account_id = AccountID::from(uuid::Uuid::new_v4());
+        let other_account_id = AccountID::from(uuid::Uuid::new_v4());
+        let sealed = encryption_key()
+            .seal(&totp_secret_name(&account_id), b"secret")
+            .unwrap();
+
+        assert_eq!(
+            encryption_key()
+                .unseal(&totp_secret_name(&account_id), &sealed)
+                .unwrap(),
+            b"secret"
+        );
+        assert!(encryption_key()
+            .unseal(&totp_secret_name(&other_account_id), &sealed)
+            .is_err());
+    }
+
+    #[test]
+    fn encryption_key_must_be_32_bytes() {
+        assert!(EncryptionKey::from_base64(&base64::encode([7; 16])).is_err());
+        assert!(EncryptionKey::from_base64("not base64").is_err());
+    }
+}
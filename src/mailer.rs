@@ -0,0 +1,220 @@
+use actix_web::{error::BlockingError, web};
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, Message, SmtpTransport,
+    Transport,
+};
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::error::APIError;
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Error, Debug)]
+pub enum MailerError {
+    #[error("Invalid address ({0})")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("Cannot build the message ({0})")]
+    Message(#[from] lettre::error::Error),
+    #[error("SMTP error ({0})")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("Cannot write to the outbox ({0})")]
+    Io(#[from] std::io::Error),
+    #[error("Mail delivery was canceled")]
+    Canceled,
+}
+
+impl<E: Into<MailerError> + std::fmt::Debug> From<BlockingError<E>> for MailerError {
+    fn from(error: BlockingError<E>) -> Self {
+        match error {
+            BlockingError::Error(error) => error.into(),
+            BlockingError::Canceled => MailerError::Canceled,
+        }
+    }
+}
+
+impl From<MailerError> for APIError {
+    fn from(error: MailerError) -> Self {
+        log::error!("Mail delivery failed: {}", error);
+        APIError::InternalError {
+            message: format!("{}", error),
+        }
+    }
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), MailerError>;
+}
+
+pub type SharedMailer = Arc<dyn Mailer>;
+
+fn build_message(from: &Mailbox, mail: Mail) -> Result<Message, MailerError> {
+    Ok(Message::builder()
+        .from(from.clone())
+        .to(mail.to.parse()?)
+        .subject(mail.subject)
+        .body(mail.body)?)
+}
+
+/// Delivers mail through an SMTP relay
+pub struct SmtpMailer {
+    transport: SmtpTransport,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: Option<u16>,
+        credentials: Option<(String, String)>,
+        from: Mailbox,
+    ) -> Result<Self, MailerError> {
+        let mut builder = SmtpTransport::relay(host)?;
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(SmtpMailer {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: Mail) -> Result<(), MailerError> {
+        let message = build_message(&self.from, mail)?;
+        let transport = self.transport.clone();
+
+        // lettre transport is blocking
+        web::block(move || transport.send(&message).map(|_| ())).await?;
+        Ok(())
+    }
+}
+
+/// Writes every mail into a directory instead of sending it. Meant for development and tests
+pub struct OutboxMailer {
+    directory: PathBuf,
+    from: Mailbox,
+}
+
+impl OutboxMailer {
+    pub fn new(directory: PathBuf, from: Mailbox) -> Self {
+        OutboxMailer { directory, from }
+    }
+}
+
+#[async_trait]
+impl Mailer for OutboxMailer {
+    async fn send(&self, mail: Mail) -> Result<(), MailerError> {
+        let message = build_message(&self.from, mail)?;
+        let path = self.directory.join(format!(
+            "{}-{}.eml",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+            uuid::Uuid::new_v4()
+        ));
+        let directory = self.directory.clone();
+
+        web::block(move || {
+            std::fs::create_dir_all(directory)?;
+            std::fs::write(path, message.formatted())
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+/// Treats empty variables as unset, compose passes unset ones through as empty strings
+fn optional_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Picks the mailer according to the `MAILER` environment variable, `outbox` being the default
+pub fn mailer_from_env() -> Result<SharedMailer, MailerError> {
+    let from: Mailbox = optional_var("MAIL_FROM")
+        .unwrap_or_else(|| "Ne Student <noreply@localhost>".to_string())
+        .parse()?;
+
+    match env::var("MAILER").as_ref().map(String::as_str) {
+        Ok("smtp") => {
+            let host = env::var("SMTP_HOST").expect("SMTP_HOST is not set");
+            let port = optional_var("SMTP_PORT")
+                .map(|port| port.parse().expect("SMTP_PORT is not a valid port"));
+            let credentials = optional_var("SMTP_USERNAME")
+                .map(|username| (username, env::var("SMTP_PASSWORD").unwrap_or_default()));
+
+            Ok(Arc::new(SmtpMailer::new(&host, port, credentials, from)?))
+        }
+        _ => {
+            let directory = optional_var("MAIL_OUTBOX_DIR").unwrap_or_else(|| "outbox".to_string());
+            Ok(Arc::new(OutboxMailer::new(directory.into(), from)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outbox_mailer() -> (OutboxMailer, PathBuf) {
+        let directory = env::temp_dir().join(format!("outbox-{}", uuid::Uuid::new_v4()));
+        let from = "Ne Student <noreply@localhost>".parse().unwrap();
+        (OutboxMailer::new(directory.clone(), from), directory)
+    }
+
+    #[actix_rt::test]
+    async fn outbox_mailer_writes_mail_into_directory() {
+        let (mailer, directory) = outbox_mailer();
+        mailer
+            .send(Mail {
+                to: "student@example.com".to_string(),
+                subject: "Password reset".to_string(),
+                body: "Use the following token".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let files = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+
+        let contents = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(contents.contains("From: \"Ne Student\" <noreply@localhost>"));
+        assert!(contents.contains("To: student@example.com"));
+        assert!(contents.contains("Subject: Password reset"));
+        assert!(contents.contains("Use the following token"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn outbox_mailer_rejects_invalid_recipient() {
+        let (mailer, directory) = outbox_mailer();
+        let result = mailer
+            .send(Mail {
+                to: "not an address".to_string(),
+                subject: "Password reset".to_string(),
+                body: String::new(),
+            })
+            .await;
+
+        assert!(matches!(result, Err(MailerError::Address(_))));
+        assert!(!directory.exists());
+    }
+}
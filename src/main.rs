@@ -11,10 +11,13 @@ use actix_rt::time::delay_for;
 use std::time::Duration;
 
 mod error;
+mod keyring;
+mod mailer;
 mod model;
 mod payload;
 mod routes;
 mod token;
+mod totp;
 mod util;
 mod middleware;
 mod types;
@@ -64,6 +67,22 @@ async fn main() -> Result<()> {
 
     let pool = wait_for_db(|| PgPool::new(&db_url)).await;
 
+    let mailer = mailer::mailer_from_env()?;
+    let admin_secret = routes::admin::AdminSecret::from_env();
+    let keyring = web::Data::new(keyring::Keyring::from_env(&pool).await?);
+
+    let reloaded_keyring = keyring.clone();
+    let keyring_pool = pool.clone();
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(keyring::RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(error) = reloaded_keyring.reload(&keyring_pool).await {
+                log::error!("Cannot reload signing keys ({})", error);
+            }
+        }
+    });
+
     let mut server = HttpServer::new(move || {
         App::new()
             .app_data(web::JsonConfig::default().error_handler(error::json_error_handler))
@@ -71,6 +90,9 @@ async fn main() -> Result<()> {
             .app_data(web::QueryConfig::default().error_handler(error::query_error_handler))
             .data(pool.clone())
             .data(redis_pool.clone())
+            .data(mailer.clone())
+            .data(admin_secret.clone())
+            .app_data(keyring.clone())
             .wrap(Compress::default())
             .wrap(NormalizePath)
             .wrap(Logger::default())
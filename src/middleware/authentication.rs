@@ -1,18 +1,23 @@
+use std::cell::RefCell;
 use std::pin::Pin;
+use std::rc::Rc;
 use std::task::{Context, Poll};
 
 use actix_service::{Service, Transform};
-use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error};
-use futures::future::{err, ok, Ready};
+use actix_web::{dev::ServiceRequest, dev::ServiceResponse, web, Error, FromRequest};
+use futures::future::{ok, Ready};
 use futures::Future;
 
-use crate::token::authenticate_claim_from_headers;
+use crate::error::APIError;
+use crate::keyring::Keyring;
+use crate::token::{authenticate_claim_from_headers, ensure_not_revoked};
+use crate::types::RedisPool;
 
 pub struct Authentication;
 
 impl<S, B> Transform<S> for Authentication
 where
-    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -24,17 +29,19 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ok(AuthenticationMiddleware { service })
+        ok(AuthenticationMiddleware {
+            service: Rc::new(RefCell::new(service)),
+        })
     }
 }
 
 pub struct AuthenticationMiddleware<S> {
-    service: S,
+    service: Rc<RefCell<S>>,
 }
 
 impl<S, B> Service for AuthenticationMiddleware<S>
 where
-    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -48,22 +55,35 @@ where
     }
 
     fn call(&mut self, req: ServiceRequest) -> Self::Future {
-        let claim = authenticate_claim_from_headers(req.headers());
-        // TODO: verify revoked tokens
+        let (http_req, mut payload) = req.into_parts();
 
-        match claim {
-            Ok(application_claim) => {
-                let (http_req, payload) = req.into_parts();
-                {
-                    let mut extensions = http_req.extensions_mut();
-                    extensions.insert(application_claim.inner.account_id);
-                    extensions.insert(application_claim);
-                }
-                let new_req =
-                    ServiceRequest::from_parts(http_req, payload).unwrap_or_else(|_| panic!("???"));
-                Box::pin(self.service.call(new_req))
+        let mut service = self.service.clone();
+
+        Box::pin(async move {
+            let keyring =
+                web::Data::<Keyring>::from_request(&http_req, &mut payload).into_inner()?;
+            let application_claim = authenticate_claim_from_headers(&keyring, http_req.headers())?;
+            let redis_pool =
+                web::Data::<RedisPool>::from_request(&http_req, &mut payload).into_inner()?;
+            let mut redis = redis_pool.get().await.map_err(APIError::from)?;
+
+            ensure_not_revoked(
+                &mut redis,
+                &application_claim.inner.account_id,
+                &application_claim.inner.token_origin,
+                application_claim.registered.issued_at,
+            )
+            .await?;
+
+            {
+                let mut extensions = http_req.extensions_mut();
+                extensions.insert(application_claim.inner.account_id);
+                extensions.insert(application_claim);
             }
-            Err(error) => Box::pin(err(Error::from(error))),
-        }
+            let new_req =
+                ServiceRequest::from_parts(http_req, payload).unwrap_or_else(|_| panic!("???"));
+
+            service.call(new_req).await
+        })
     }
 }
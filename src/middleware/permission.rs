@@ -104,10 +104,16 @@ where
                 .await
                 .map_err(|error| Error::from(APIError::from(error)))?;
 
-            if let (Some(PermissionType::ReadWrite), PermissionType::Read) =
-                (expected_permission, permission.permission())
-            {
-                return Err(APIError::NoWriteAccess.into());
+            match expected_permission {
+                Some(expected) if permission.permission() < expected => {
+                    return Err(match expected {
+                        PermissionType::Owner => APIError::NotAnOwner,
+                        PermissionType::ReadWrite => APIError::NoWriteAccess,
+                        PermissionType::Read => APIError::NoReadAccess,
+                    }
+                    .into());
+                }
+                _ => {}
             }
 
             http_req.extensions_mut().insert(permission);
@@ -5,6 +5,10 @@ use thiserror::Error;
 use indoc::indoc;
 
 use crate::error::APIError;
+use crate::model::permission::{
+    EntityPermission, GroupPermission, LessonPermission, TaskPermission, TeacherPermission,
+};
+use crate::types::Transaction;
 use crate::uuid_wrapper;
 
 uuid_wrapper!(AccountID);
@@ -16,6 +20,7 @@ pub struct Account {
     pub last_name: Option<String>,
     pub login: String,
     pub password_hash: String,
+    pub email: Option<String>,
 }
 
 #[derive(Error, Debug)]
@@ -38,10 +43,37 @@ impl From<RegistrationError> for APIError {
     }
 }
 
+#[derive(Error, Debug)]
+pub enum PasswordChangeError {
+    #[error("{0}")]
+    Database(#[from] sqlx::Error),
+    #[error("{0}")]
+    Bcrypt(#[from] BcryptError),
+}
+
+impl From<PasswordChangeError> for APIError {
+    fn from(err: PasswordChangeError) -> Self {
+        match err {
+            PasswordChangeError::Database(error) => error.into(),
+            PasswordChangeError::Bcrypt(error) => error.into(),
+        }
+    }
+}
+
 impl Account {
+    pub async fn by_id(db: &PgPool, account_id: &AccountID) -> sqlx::Result<Option<Account>> {
+        sqlx::query_as(indoc! {"
+            SELECT id, first_name, last_name, login, password_hash, email
+            FROM Account WHERE id = $1
+        "})
+        .bind(account_id)
+        .fetch_optional(db)
+        .await
+    }
+
     pub async fn get_by_login(db: &PgPool, login: String) -> sqlx::Result<Option<Account>> {
         sqlx::query_as(indoc! {"
-            SELECT id, first_name, last_name, login, password_hash 
+            SELECT id, first_name, last_name, login, password_hash, email
             FROM Account WHERE login = $1
         "})
         .bind(&login)
@@ -49,6 +81,36 @@ impl Account {
         .await
     }
 
+    pub async fn by_email(db: &PgPool, email: &str) -> sqlx::Result<Option<Account>> {
+        sqlx::query_as(indoc! {"
+            SELECT id, first_name, last_name, login, password_hash, email
+            FROM Account WHERE lower(email) = lower($1)
+        "})
+        .bind(email)
+        .fetch_optional(db)
+        .await
+    }
+
+    /// Whether the email is used by an account other than `except`
+    async fn email_taken(
+        transaction: &mut Transaction,
+        email: &str,
+        except: &AccountID,
+    ) -> sqlx::Result<bool> {
+        let (taken,): (bool,) = sqlx::query_as(indoc! {"
+            SELECT EXISTS (
+                SELECT FROM Account
+                WHERE lower(email) = lower($1) AND id <> $2
+            )
+        "})
+        .bind(email)
+        .bind(except)
+        .fetch_one(transaction)
+        .await?;
+
+        Ok(taken)
+    }
+
     pub async fn register(
         db: &PgPool,
         first_name: String,
@@ -89,6 +151,69 @@ impl Account {
             last_name,
             login,
             password_hash: hash,
+            email: None,
         })
     }
+
+    /// Sets an email whose ownership has been confirmed, or clears it.
+    /// Returns `false` when the email is already used by another account
+    pub async fn set_email(
+        db: &PgPool,
+        account_id: &AccountID,
+        email: Option<String>,
+    ) -> sqlx::Result<bool> {
+        let mut transaction = db.begin().await?;
+
+        if let Some(email) = &email {
+            if Self::email_taken(&mut transaction, email, account_id).await? {
+                return Ok(false);
+            }
+        }
+
+        sqlx::query("UPDATE Account SET email = $1 WHERE id = $2")
+            .bind(&email)
+            .bind(account_id)
+            .execute(&mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+
+    pub async fn change_password(
+        db: &PgPool,
+        account_id: &AccountID,
+        password: String,
+    ) -> Result<(), PasswordChangeError> {
+        let hash = bcrypt::hash(password, 8)?;
+
+        sqlx::query("UPDATE Account SET password_hash = $1 WHERE id = $2")
+            .bind(&hash)
+            .bind(account_id)
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Removes the account together with its permissions.
+    /// Solely owned entities are passed to their writers or deleted when there are none
+    pub async fn delete(db: &PgPool, account_id: &AccountID) -> sqlx::Result<()> {
+        let mut transaction = db.begin().await?;
+
+        LessonPermission::release_ownership_in_transaction(&mut transaction, account_id).await?;
+        TeacherPermission::release_ownership_in_transaction(&mut transaction, account_id).await?;
+        TaskPermission::release_ownership_in_transaction(&mut transaction, account_id).await?;
+        GroupPermission::release_ownership_in_transaction(&mut transaction, account_id).await?;
+
+        sqlx::query("DELETE FROM Account WHERE id = $1")
+            .bind(account_id)
+            .execute(&mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,91 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use indoc::indoc;
+use serde::Serialize;
+use sqlx::postgres::{PgPool, PgQueryAs};
+use std::vec::Vec;
+
+use super::account::AccountID;
+use super::lesson::LessonID;
+use super::repeat::DEFAULT_DURATION;
+use super::teacher::TeacherID;
+
+/// Kind of the schedule entry which produced an occurrence
+#[derive(Serialize, Debug, Copy, Clone, Eq, PartialEq, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[repr(i16)]
+pub enum RepeatKind {
+    Single = 1,
+    Daily = 2,
+    Weekly = 3,
+    Monthly = 4,
+    /// Moved by a lesson exception
+    Rescheduled = 5,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct Occurrence {
+    pub lesson_id: LessonID,
+    pub title: String,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+    pub kind: RepeatKind,
+}
+
+impl Occurrence {
+    /// Occurrences of readable lessons, optionally only the ones taught by `teacher_id`
+    pub async fn between(
+        db: &PgPool,
+        from: &NaiveDate,
+        to: &NaiveDate,
+        teacher_id: &Option<TeacherID>,
+        account_id: &AccountID,
+    ) -> sqlx::Result<Vec<Occurrence>> {
+        sqlx::query_as(indoc! {"
+            SELECT
+                x.lesson_id,
+                Lesson.title,
+                x.starts_at,
+                x.starts_at + x.duration * INTERVAL '1 minute' AS ends_at,
+                x.kind
+            FROM (
+                SELECT lesson_id, occurs_at AS starts_at, duration, 1::SMALLINT AS kind
+                FROM SingleOccurrence
+                WHERE occurs_at::DATE BETWEEN $1 AND $2
+                UNION ALL
+                SELECT lesson_id, candidate::DATE + scheduled_time, duration, 2::SMALLINT
+                FROM LessonDailyRepeat,
+                    generate_series($1::TIMESTAMP, $2::TIMESTAMP, '1 day'::INTERVAL) candidate
+                WHERE repeats_on_date_daily(start_date, end_date, candidate::DATE)
+                UNION ALL
+                SELECT lesson_id, candidate::DATE + scheduled_time, duration, 3::SMALLINT
+                FROM LessonWeeklyRepeat,
+                    generate_series($1::TIMESTAMP, $2::TIMESTAMP, '1 day'::INTERVAL) candidate
+                WHERE repeats_on_date_weekly(start_date, end_date, week_day, every, candidate::DATE)
+                UNION ALL
+                SELECT lesson_id, candidate::DATE + scheduled_time::TIME, duration, 4::SMALLINT
+                FROM LessonMonthlyRepeat,
+                    generate_series($1::TIMESTAMP, $2::TIMESTAMP, '1 day'::INTERVAL) candidate
+                WHERE repeats_on_date_monthly(start_date, end_date, scheduled_time::DATE, every, candidate::DATE)
+                UNION ALL
+                SELECT lesson_id, moved_to, COALESCE(duration, $5), 5::SMALLINT
+                FROM LessonException
+                WHERE moved_to::DATE BETWEEN $1 AND $2
+            ) x
+            JOIN Lesson ON Lesson.id = x.lesson_id
+            WHERE is_read_permission(lesson_permission_for(x.lesson_id, $3))
+                AND (x.kind = 5 OR NOT is_excepted_on(x.lesson_id, x.starts_at::DATE))
+                AND ($4::UUID IS NULL OR EXISTS (
+                    SELECT FROM TeacherLesson
+                    WHERE TeacherLesson.lesson_id = x.lesson_id AND TeacherLesson.teacher_id = $4
+                ))
+            ORDER BY x.starts_at
+        "})
+        .bind(from)
+        .bind(to)
+        .bind(account_id)
+        .bind(teacher_id)
+        .bind(DEFAULT_DURATION)
+        .fetch_all(db)
+        .await
+    }
+}
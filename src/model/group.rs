@@ -0,0 +1,87 @@
+use chrono::NaiveDateTime;
+use indoc::indoc;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgPool, PgQueryAs};
+
+use super::account::AccountID;
+use super::permission::{EntityPermission, GroupPermission, PermissionType};
+use crate::uuid_wrapper;
+
+uuid_wrapper!(GroupID);
+
+/// Student group. Lessons and teachers shared with a group are accessible to all of its members
+#[derive(Serialize, Deserialize, Clone, Debug, sqlx::FromRow)]
+pub struct Group {
+    id: GroupID,
+    name: String,
+    created_at: NaiveDateTime,
+}
+
+impl Group {
+    pub async fn by_id(db: &PgPool, group_id: GroupID) -> sqlx::Result<Option<Group>> {
+        sqlx::query_as("SELECT id, name, created_at FROM StudentGroup WHERE id = $1")
+            .bind(group_id)
+            .fetch_optional(db)
+            .await
+    }
+
+    pub async fn create(db: &PgPool, name: String, owner: &AccountID) -> sqlx::Result<Group> {
+        let mut transaction = db.begin().await?;
+
+        let (id, created_at): (GroupID, NaiveDateTime) =
+            sqlx::query_as("INSERT INTO StudentGroup (name) VALUES ($1) RETURNING id, created_at")
+                .bind(&name)
+                .fetch_one(&mut transaction)
+                .await?;
+
+        GroupPermission::save_in_transaction(&mut transaction, PermissionType::Owner, &id, owner)
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(Group {
+            id,
+            name,
+            created_at,
+        })
+    }
+
+    pub async fn rename(db: &PgPool, group_id: &GroupID, name: String) -> sqlx::Result<()> {
+        sqlx::query("UPDATE StudentGroup SET name = $1 WHERE id = $2")
+            .bind(name)
+            .bind(group_id)
+            .execute(db)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn delete(db: &PgPool, group_id: &GroupID) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM StudentGroup WHERE id = $1")
+            .bind(group_id)
+            .execute(db)
+            .await
+            .map(|_| ())
+    }
+
+    /// Groups the account is a member of
+    pub async fn of_user(db: &PgPool, account_id: &AccountID) -> sqlx::Result<Vec<Group>> {
+        sqlx::query_as(indoc! {"
+            SELECT id, name, created_at
+            FROM StudentGroup
+            JOIN GroupPermission ON StudentGroup.id = GroupPermission.group_id
+            WHERE GroupPermission.account_id = $1
+            ORDER BY name
+        "})
+        .bind(account_id)
+        .fetch_all(db)
+        .await
+    }
+
+    pub async fn leave(
+        db: &PgPool,
+        group_id: &GroupID,
+        account_id: &AccountID,
+    ) -> sqlx::Result<()> {
+        GroupPermission::revoke(db, group_id, account_id).await
+    }
+}
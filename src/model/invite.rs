@@ -0,0 +1,66 @@
+use chrono::NaiveDateTime;
+use indoc::indoc;
+use sqlx::postgres::{PgPool, PgQueryAs};
+
+use super::account::AccountID;
+use crate::types::Transaction;
+use crate::uuid_wrapper;
+
+uuid_wrapper!(InviteID);
+
+/// Use accounting of the invite links. Shared entity and permission are carried by the token itself
+pub struct Invite;
+
+impl Invite {
+    pub async fn create(
+        db: &PgPool,
+        issuer: &AccountID,
+        max_uses: Option<i32>,
+        expires_at: &NaiveDateTime,
+    ) -> sqlx::Result<InviteID> {
+        let (id,): (InviteID,) = sqlx::query_as(
+            "INSERT INTO Invite (issuer_id, max_uses, expires_at) VALUES ($1, $2, $3) RETURNING id",
+        )
+        .bind(issuer)
+        .bind(max_uses)
+        .bind(expires_at)
+        .fetch_one(db)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Counts a use of the invite, returning its issuer. The invite stays locked until the transaction ends.
+    /// Nothing is returned if the invite was revoked, has expired or ran out of uses
+    pub async fn redeem_in_transaction(
+        transaction: &mut Transaction,
+        invite_id: &InviteID,
+    ) -> sqlx::Result<Option<AccountID>> {
+        let issuer: Option<(AccountID,)> = sqlx::query_as(indoc! {"
+            UPDATE Invite SET uses = uses + 1
+            WHERE id = $1
+                AND expires_at > (now() AT TIME ZONE 'utc')
+                AND (max_uses IS NULL OR uses < max_uses)
+            RETURNING issuer_id
+        "})
+        .bind(invite_id)
+        .fetch_optional(transaction)
+        .await?;
+
+        Ok(issuer.map(|(issuer,)| issuer))
+    }
+
+    /// Returns `false` when the invite does not exist or was issued by another account
+    pub async fn revoke(
+        db: &PgPool,
+        invite_id: &InviteID,
+        issuer: &AccountID,
+    ) -> sqlx::Result<bool> {
+        sqlx::query("DELETE FROM Invite WHERE id = $1 AND issuer_id = $2")
+            .bind(invite_id)
+            .bind(issuer)
+            .execute(db)
+            .await
+            .map(|affected| affected > 0)
+    }
+}
@@ -1,4 +1,4 @@
-use chrono::NaiveDate;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
 use indoc::indoc;
 use serde::{Deserialize, Serialize};
 use sqlx::postgres::{PgPool, PgQueryAs, Postgres};
@@ -6,8 +6,12 @@ use std::vec::Vec;
 
 use super::account::AccountID;
 use super::permission::{EntityPermission, LessonPermission, PermissionType};
-use super::repeat::{DailyRepeat, MonthlyRepeat, SingleOccurrence, WeeklyRepeat};
+use super::repeat::{
+    DailyRepeat, MonthlyRepeat, Recurrence, RepeatException, SingleOccurrence, TimeSpan,
+    WeeklyRepeat,
+};
 use super::teacher::TeacherID;
+use super::templated_insert;
 use crate::types::Transaction;
 use crate::uuid_wrapper;
 
@@ -23,6 +27,7 @@ pub struct Lesson {
     pub weekly: Vec<WeeklyRepeat>,
     pub daily: Vec<DailyRepeat>,
     pub monthly: Vec<MonthlyRepeat>,
+    pub exceptions: Vec<RepeatException>,
     pub teachers: Vec<TeacherID>,
 }
 
@@ -33,6 +38,48 @@ struct LessonBase {
 }
 
 impl Lesson {
+    pub fn recurrences(&self) -> impl Iterator<Item = &dyn Recurrence> {
+        self.singles
+            .iter()
+            .map(|r| r as &dyn Recurrence)
+            .chain(self.daily.iter().map(|r| r as &dyn Recurrence))
+            .chain(self.weekly.iter().map(|r| r as &dyn Recurrence))
+            .chain(self.monthly.iter().map(|r| r as &dyn Recurrence))
+    }
+
+    fn is_excepted_on(&self, date: &NaiveDate) -> bool {
+        self.exceptions
+            .iter()
+            .any(|exception| exception.original_date == *date)
+    }
+
+    /// Sorted occurrences within the range, with exceptions applied
+    pub fn occurrences_between(&self, from: &NaiveDate, to: &NaiveDate) -> Vec<TimeSpan> {
+        let mut res: Vec<TimeSpan> = self
+            .recurrences()
+            .flat_map(|recurrence| recurrence.occurrences_between(from, to))
+            .filter(|occurrence| !self.is_excepted_on(&occurrence.starts_at.date()))
+            .chain(
+                self.exceptions
+                    .iter()
+                    .filter_map(RepeatException::moved_span)
+                    .filter(|moved| {
+                        *from <= moved.starts_at.date() && moved.starts_at.date() <= *to
+                    }),
+            )
+            .collect();
+        res.sort();
+        res
+    }
+
+    /// First occurrence starting at `now` or later, looking one year ahead at most
+    pub fn next_occurrence_from(&self, now: &NaiveDateTime) -> Option<TimeSpan> {
+        let until = now.date() + Duration::days(366);
+        self.occurrences_between(&now.date(), &until)
+            .into_iter()
+            .find(|occurrence| occurrence.starts_at >= *now)
+    }
+
     async fn teachers_of_lesson_by_id_in_transaction(
         transaction: &mut Transaction,
         lesson_id: &LessonID,
@@ -48,31 +95,86 @@ impl Lesson {
         .collect())
     }
 
-    pub async fn by_id(db: &PgPool, lesson_id: LessonID) -> sqlx::Result<Option<Lesson>> {
-        let mut transaction = db.begin().await?;
+    async fn insert_teachers_in_transaction(
+        transaction: &mut Transaction,
+        teachers: &Vec<TeacherID>,
+        lesson_id: &LessonID,
+    ) -> sqlx::Result<()> {
+        if !teachers.is_empty() {
+            let values = (0..teachers.len())
+                .map(|i| templated_insert(2, i))
+                .collect::<Vec<String>>()
+                .join(",");
+
+            let sql = format!(
+                "INSERT INTO TeacherLesson (teacher_id, lesson_id) VALUES {} ON CONFLICT DO NOTHING",
+                values
+            );
+
+            let mut query = sqlx::query(&sql[..]);
+
+            for teacher_id in teachers {
+                query = query.bind(teacher_id).bind(lesson_id);
+            }
+            query.execute(transaction).await?;
+        }
 
+        Ok(())
+    }
+
+    pub async fn assign_teacher(
+        db: &PgPool,
+        lesson_id: &LessonID,
+        teacher_id: &TeacherID,
+    ) -> sqlx::Result<()> {
+        sqlx::query(
+            "INSERT INTO TeacherLesson (teacher_id, lesson_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(teacher_id)
+        .bind(lesson_id)
+        .execute(db)
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn unassign_teacher(
+        db: &PgPool,
+        lesson_id: &LessonID,
+        teacher_id: &TeacherID,
+    ) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM TeacherLesson WHERE teacher_id = $1 AND lesson_id = $2")
+            .bind(teacher_id)
+            .bind(lesson_id)
+            .execute(db)
+            .await
+            .map(|_| ())
+    }
+
+    async fn by_id_in_transaction(
+        transaction: &mut Transaction,
+        lesson_id: LessonID,
+    ) -> sqlx::Result<Option<Lesson>> {
         let base = sqlx::query_as("SELECT title, description FROM Lesson WHERE id = $1")
             .bind(&lesson_id)
-            .fetch_optional(&mut transaction)
+            .fetch_optional(&mut *transaction)
             .await?;
 
-        let teachers =
-            Lesson::teachers_of_lesson_by_id_in_transaction(&mut transaction, &lesson_id).await?;
-
         Ok(match base {
             None => None,
             Some(LessonBase { description, title }) => {
                 let singles =
-                    SingleOccurrence::of_lesson_in_transaction(&mut transaction, &lesson_id)
-                        .await?;
-                let daily =
-                    DailyRepeat::of_lesson_in_transaction(&mut transaction, &lesson_id).await?;
-                let weekly =
-                    WeeklyRepeat::of_lesson_in_transaction(&mut transaction, &lesson_id).await?;
+                    SingleOccurrence::of_lesson_in_transaction(transaction, &lesson_id).await?;
+                let daily = DailyRepeat::of_lesson_in_transaction(transaction, &lesson_id).await?;
+                let weekly = WeeklyRepeat::of_lesson_in_transaction(transaction, &lesson_id).await?;
                 let monthly =
-                    MonthlyRepeat::of_lesson_in_transaction(&mut transaction, &lesson_id).await?;
+                    MonthlyRepeat::of_lesson_in_transaction(transaction, &lesson_id).await?;
+                let exceptions =
+                    RepeatException::of_lesson_in_transaction(transaction, &lesson_id).await?;
+                let teachers =
+                    Lesson::teachers_of_lesson_by_id_in_transaction(transaction, &lesson_id)
+                        .await?;
 
-                let res = Lesson {
+                Some(Lesson {
                     id: lesson_id,
                     title,
                     description,
@@ -80,15 +182,20 @@ impl Lesson {
                     daily,
                     weekly,
                     monthly,
+                    exceptions,
                     teachers,
-                };
-
-                transaction.commit().await?;
-                Some(res)
+                })
             }
         })
     }
 
+    pub async fn by_id(db: &PgPool, lesson_id: LessonID) -> sqlx::Result<Option<Lesson>> {
+        let mut transaction = db.begin().await?;
+        let res = Lesson::by_id_in_transaction(&mut transaction, lesson_id).await?;
+        transaction.commit().await?;
+        Ok(res)
+    }
+
     pub async fn create(
         db: &PgPool,
         title: String,
@@ -97,6 +204,8 @@ impl Lesson {
         daily: Vec<DailyRepeat>,
         weekly: Vec<WeeklyRepeat>,
         monthly: Vec<MonthlyRepeat>,
+        exceptions: Vec<RepeatException>,
+        teachers: Vec<TeacherID>,
         owner: &AccountID,
     ) -> sqlx::Result<Lesson> {
         let mut transaction = db.begin().await?;
@@ -112,10 +221,12 @@ impl Lesson {
         DailyRepeat::insert_in_transaction(&mut transaction, &daily, &id).await?;
         WeeklyRepeat::insert_in_transaction(&mut transaction, &weekly, &id).await?;
         MonthlyRepeat::insert_in_transaction(&mut transaction, &monthly, &id).await?;
+        RepeatException::insert_in_transaction(&mut transaction, &exceptions, &id).await?;
+        Lesson::insert_teachers_in_transaction(&mut transaction, &teachers, &id).await?;
 
         LessonPermission::save_in_transaction(
             &mut transaction,
-            PermissionType::ReadWrite,
+            PermissionType::Owner,
             &id,
             &owner,
         )
@@ -131,7 +242,8 @@ impl Lesson {
             daily,
             weekly,
             monthly,
-            teachers: Vec::new(),
+            exceptions,
+            teachers,
         })
     }
 
@@ -143,6 +255,8 @@ impl Lesson {
         daily: &Option<Vec<DailyRepeat>>,
         weekly: &Option<Vec<WeeklyRepeat>>,
         monthly: &Option<Vec<MonthlyRepeat>>,
+        exceptions: &Option<Vec<RepeatException>>,
+        teachers: &Option<Vec<TeacherID>>,
         description: &Option<Option<String>>,
     ) -> sqlx::Result<()> {
         let mut transaction = db.begin().await?;
@@ -182,6 +296,19 @@ impl Lesson {
             MonthlyRepeat::update_in_transaction(&mut transaction, repeats, lesson_id).await?;
         }
 
+        if let Some(exceptions) = exceptions {
+            RepeatException::update_in_transaction(&mut transaction, exceptions, lesson_id)
+                .await?;
+        }
+
+        if let Some(teachers) = teachers {
+            sqlx::query("DELETE FROM TeacherLesson WHERE lesson_id = $1")
+                .bind(lesson_id)
+                .execute(&mut transaction)
+                .await?;
+            Lesson::insert_teachers_in_transaction(&mut transaction, teachers, lesson_id).await?;
+        }
+
         transaction.commit().await?;
 
         Ok(())
@@ -204,20 +331,26 @@ impl Lesson {
 
         // TODO: Optimize query to first query user-accessible lessons
         let ids = sqlx::query_as::<_, (LessonID,)>(indoc! {"
-            SELECT x.lesson_id FROM (
-                SELECT DISTINCT lesson_id FROM SingleOccurrence 
-                WHERE occurs_at BETWEEN $1 AND $1 + 1
-                UNION
-                SELECT DISTINCT lesson_id FROM LessonDailyRepeat
-                WHERE repeats_on_date_daily(start_date, end_date, $1)
-                UNION
-                SELECT DISTINCT lesson_id FROM LessonWeeklyRepeat 
-                WHERE repeats_on_date_weekly(start_date, end_date, week_day, every, $1)
+            SELECT y.lesson_id FROM (
+                SELECT x.lesson_id FROM (
+                    SELECT DISTINCT lesson_id FROM SingleOccurrence 
+                    WHERE occurs_at BETWEEN $1 AND $1 + 1
+                    UNION
+                    SELECT DISTINCT lesson_id FROM LessonDailyRepeat
+                    WHERE repeats_on_date_daily(start_date, end_date, $1)
+                    UNION
+                    SELECT DISTINCT lesson_id FROM LessonWeeklyRepeat 
+                    WHERE repeats_on_date_weekly(start_date, end_date, week_day, every, $1)
+                    UNION
+                    SELECT DISTINCT lesson_id FROM LessonMonthlyRepeat 
+                    WHERE repeats_on_date_monthly(start_date, end_date, scheduled_time::DATE, every, $1)
+                ) x
+                WHERE NOT is_excepted_on(x.lesson_id, $1)
                 UNION
-                SELECT DISTINCT lesson_id FROM LessonMonthlyRepeat 
-                WHERE repeats_on_date_monthly(start_date, end_date, scheduled_time::DATE, every, $1)
-            ) x
-            WHERE is_read_permission(lesson_permission_for(x.lesson_id, $2))
+                SELECT DISTINCT lesson_id FROM LessonException
+                WHERE moved_to::DATE = $1
+            ) y
+            WHERE is_read_permission(lesson_permission_for(y.lesson_id, $2))
         "})
         .bind(date)
         .bind(account_id)
@@ -227,48 +360,36 @@ impl Lesson {
         let mut res = Vec::<Lesson>::with_capacity(ids.len());
         // TODO: Optimize N+1 queries
         for (lesson_id,) in ids {
-            let base = sqlx::query_as("SELECT title, description FROM Lesson WHERE id = $1")
-                .bind(&lesson_id)
-                .fetch_optional(&mut transaction)
-                .await?;
+            if let Some(lesson) = Lesson::by_id_in_transaction(&mut transaction, lesson_id).await? {
+                res.push(lesson);
+            }
+        }
 
-            let lesson = match base {
-                None => None,
-                Some(LessonBase { description, title }) => {
-                    let singles =
-                        SingleOccurrence::of_lesson_in_transaction(&mut transaction, &lesson_id)
-                            .await?;
-                    let daily =
-                        DailyRepeat::of_lesson_in_transaction(&mut transaction, &lesson_id).await?;
-                    let weekly =
-                        WeeklyRepeat::of_lesson_in_transaction(&mut transaction, &lesson_id)
-                            .await?;
-                    let monthly =
-                        MonthlyRepeat::of_lesson_in_transaction(&mut transaction, &lesson_id)
-                            .await?;
-
-                    let teachers = Lesson::teachers_of_lesson_by_id_in_transaction(
-                        &mut transaction,
-                        &lesson_id,
-                    )
-                    .await?;
-
-                    let res = Lesson {
-                        id: lesson_id,
-                        title,
-                        description,
-                        singles,
-                        daily,
-                        weekly,
-                        monthly,
-                        teachers,
-                    };
-
-                    Some(res)
-                }
-            };
+        transaction.commit().await?;
+
+        Ok(res)
+    }
+
+    /// Lessons taught by the teacher which are readable by the account
+    pub async fn of_teacher(
+        db: &PgPool,
+        teacher_id: &TeacherID,
+        account_id: &AccountID,
+    ) -> sqlx::Result<Vec<Lesson>> {
+        let mut transaction = db.begin().await?;
 
-            if let Some(lesson) = lesson {
+        let ids = sqlx::query_as::<_, (LessonID,)>(indoc! {"
+            SELECT lesson_id FROM TeacherLesson
+            WHERE teacher_id = $1 AND is_read_permission(lesson_permission_for(lesson_id, $2))
+        "})
+        .bind(teacher_id)
+        .bind(account_id)
+        .fetch_all(&mut transaction)
+        .await?;
+
+        let mut res = Vec::<Lesson>::with_capacity(ids.len());
+        for (lesson_id,) in ids {
+            if let Some(lesson) = Lesson::by_id_in_transaction(&mut transaction, lesson_id).await? {
                 res.push(lesson);
             }
         }
@@ -278,3 +399,72 @@ impl Lesson {
         Ok(res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::repeat::test_util::date;
+
+    /// Wednesdays at 08:30 from 2021-03-01, with the lesson of 2021-03-10 cancelled
+    /// and the one of 2021-03-17 moved to Friday
+    fn lesson() -> Lesson {
+        serde_json::from_value(serde_json::json!({
+            "id": "00000000-0000-0000-0000-000000000000",
+            "title": "Math",
+            "singles": [],
+            "weekly": [{"every": 7, "day": 3, "at": "08:30:00", "start_date": "2021-03-01"}],
+            "daily": [],
+            "monthly": [],
+            "exceptions": [
+                {"date": "2021-03-10"},
+                {"date": "2021-03-17", "moved_to": "2021-03-19T14:00:00", "duration": 60}
+            ],
+            "teachers": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn occurrences_between_applies_exceptions() {
+        let occurrences = lesson().occurrences_between(&date(2021, 3, 1), &date(2021, 3, 24));
+        assert_eq!(
+            occurrences,
+            vec![
+                TimeSpan {
+                    starts_at: date(2021, 3, 3).and_hms(8, 30, 0),
+                    ends_at: date(2021, 3, 3).and_hms(10, 0, 0),
+                },
+                TimeSpan {
+                    starts_at: date(2021, 3, 19).and_hms(14, 0, 0),
+                    ends_at: date(2021, 3, 19).and_hms(15, 0, 0),
+                },
+                TimeSpan {
+                    starts_at: date(2021, 3, 24).and_hms(8, 30, 0),
+                    ends_at: date(2021, 3, 24).and_hms(10, 0, 0),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn moved_occurrence_outside_range_is_left_out() {
+        let occurrences = lesson().occurrences_between(&date(2021, 3, 15), &date(2021, 3, 18));
+        assert!(occurrences.is_empty());
+    }
+
+    #[test]
+    fn next_occurrence_includes_later_today() {
+        let next = lesson()
+            .next_occurrence_from(&date(2021, 3, 3).and_hms(8, 0, 0))
+            .unwrap();
+        assert_eq!(next.starts_at, date(2021, 3, 3).and_hms(8, 30, 0));
+    }
+
+    #[test]
+    fn next_occurrence_skips_started_and_cancelled() {
+        let next = lesson()
+            .next_occurrence_from(&date(2021, 3, 3).and_hms(8, 31, 0))
+            .unwrap();
+        assert_eq!(next.starts_at, date(2021, 3, 19).and_hms(14, 0, 0));
+    }
+}
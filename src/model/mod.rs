@@ -1,8 +1,15 @@
 pub mod account;
+pub mod calendar;
+pub mod group;
+pub mod invite;
 pub mod lesson;
 pub mod permission;
 pub mod repeat;
+pub mod session;
+pub mod signing_key;
+pub mod task;
 pub mod teacher;
+pub mod totp;
 
 pub fn templated_insert(size: usize, iteration: usize) -> String {
     format!("({})", (0..size).into_iter().map(|i| format!("${}", iteration * size + i + 1)).collect::<Vec<String>>().join(", "))
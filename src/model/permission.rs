@@ -5,7 +5,9 @@ use sqlx::PgPool;
 use thiserror::Error;
 
 use crate::error::APIError;
-use crate::model::{account::AccountID, lesson::LessonID, teacher::TeacherID};
+use crate::model::{
+    account::AccountID, group::GroupID, lesson::LessonID, task::TaskID, teacher::TeacherID,
+};
 use crate::types::Transaction;
 
 #[derive(Debug, sqlx::Type)]
@@ -13,6 +15,7 @@ use crate::types::Transaction;
 pub enum PgPermissionType {
     R,
     RW,
+    Owner,
 }
 
 impl From<PermissionType> for PgPermissionType {
@@ -20,16 +23,30 @@ impl From<PermissionType> for PgPermissionType {
         match pt {
             PermissionType::Read => PgPermissionType::R,
             PermissionType::ReadWrite => PgPermissionType::RW,
+            PermissionType::Owner => PgPermissionType::Owner,
         }
     }
 }
 
+/// Kinds of entities access can be shared on
 #[derive(Debug, Deserialize, Serialize, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityType {
+    Lesson,
+    Teacher,
+    Task,
+    Group,
+}
+
+/// Permission levels, ordered from the weakest to the strongest
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum PermissionType {
     #[serde(rename = "r")]
     Read,
     #[serde(rename = "rw")]
     ReadWrite,
+    #[serde(rename = "owner")]
+    Owner,
 }
 
 impl From<PgPermissionType> for PermissionType {
@@ -37,6 +54,7 @@ impl From<PgPermissionType> for PermissionType {
         match pt {
             PgPermissionType::R => PermissionType::Read,
             PgPermissionType::RW => PermissionType::ReadWrite,
+            PgPermissionType::Owner => PermissionType::Owner,
         }
     }
 }
@@ -63,10 +81,43 @@ impl From<PermissionError> for APIError {
 
 pub type Result<T> = std::result::Result<T, PermissionError>;
 
+/// Account which was granted a permission on an entity
+#[derive(Debug, Serialize, Clone)]
+pub struct PermissionHolder {
+    pub account_id: AccountID,
+    pub login: String,
+    pub first_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_name: Option<String>,
+    pub permission: PermissionType,
+}
+
+impl From<(AccountID, String, String, Option<String>, PgPermissionType)> for PermissionHolder {
+    fn from(
+        (account_id, login, first_name, last_name, permission): (
+            AccountID,
+            String,
+            String,
+            Option<String>,
+            PgPermissionType,
+        ),
+    ) -> Self {
+        PermissionHolder {
+            account_id,
+            login,
+            first_name,
+            last_name,
+            permission: permission.into(),
+        }
+    }
+}
+
 #[async_trait]
 pub trait EntityPermission: Sized + FromRequest {
     type EntityID: FromRequest<Error = crate::error::APIError>;
 
+    const ENTITY_TYPE: EntityType;
+
     fn permission(&self) -> PermissionType;
 
     async fn of_entity(
@@ -87,11 +138,101 @@ pub trait EntityPermission: Sized + FromRequest {
         entity_id: &Self::EntityID,
         account_id: &AccountID,
     ) -> sqlx::Result<()>;
+
+    async fn list(db: &PgPool, entity_id: &Self::EntityID) -> sqlx::Result<Vec<PermissionHolder>>;
+
+    /// Creates or replaces the permission of the account
+    async fn grant(
+        db: &PgPool,
+        permission_type: PermissionType,
+        entity_id: &Self::EntityID,
+        account_id: &AccountID,
+    ) -> sqlx::Result<()>;
+
+    /// Creates the permission of the account or raises the one it has.
+    /// Returns `false` if the account already had the same or a stronger permission
+    async fn strengthen_in_transaction(
+        transaction: &mut Transaction,
+        permission_type: PermissionType,
+        entity_id: &Self::EntityID,
+        account_id: &AccountID,
+    ) -> sqlx::Result<bool>;
+
+    async fn revoke(
+        db: &PgPool,
+        entity_id: &Self::EntityID,
+        account_id: &AccountID,
+    ) -> sqlx::Result<()>;
+
+    /// Makes `to` the owner of the entity, leaving `from` with write access
+    async fn transfer_ownership(
+        db: &PgPool,
+        entity_id: &Self::EntityID,
+        from: &AccountID,
+        to: &AccountID,
+    ) -> sqlx::Result<()>;
+
+    /// Hands entities solely owned by the account over to one of their writers.
+    /// Entities nobody else can write to are deleted
+    async fn release_ownership_in_transaction(
+        transaction: &mut Transaction,
+        account_id: &AccountID,
+    ) -> sqlx::Result<()>;
+}
+
+/// Group which was granted a permission on an entity
+#[derive(Debug, Serialize, Clone)]
+pub struct GroupGrant {
+    pub group_id: GroupID,
+    pub name: String,
+    pub permission: PermissionType,
+}
+
+impl From<(GroupID, String, PgPermissionType)> for GroupGrant {
+    fn from((group_id, name, permission): (GroupID, String, PgPermissionType)) -> Self {
+        GroupGrant {
+            group_id,
+            name,
+            permission: permission.into(),
+        }
+    }
+}
+
+/// Entities which can be shared with every member of a group at once
+#[async_trait]
+pub trait GroupEntityPermission: EntityPermission {
+    async fn list_groups(db: &PgPool, entity_id: &Self::EntityID) -> sqlx::Result<Vec<GroupGrant>>;
+
+    /// Creates or replaces the permission of the group
+    async fn grant_to_group(
+        db: &PgPool,
+        permission_type: PermissionType,
+        entity_id: &Self::EntityID,
+        group_id: &GroupID,
+    ) -> sqlx::Result<()>;
+
+    async fn revoke_from_group(
+        db: &PgPool,
+        entity_id: &Self::EntityID,
+        group_id: &GroupID,
+    ) -> sqlx::Result<()>;
 }
 
 #[macro_export]
 macro_rules! impl_permission {
-    ($type:ty, $id_type:ty, $column_name:expr, $entity_type_table:expr, $entity_permission_table:expr) => {
+    ($type:ty, $id_type:ty, $entity_type:expr, $column_name:expr, $entity_type_table:expr, $entity_permission_table:expr) => {
+        impl_permission!(
+            $type,
+            $id_type,
+            $entity_type,
+            $column_name,
+            $entity_type_table,
+            $entity_permission_table,
+            $entity_permission_table
+        );
+    };
+    // Effective permission table may combine several permission sources, strongest one is used
+    ($type:ty, $id_type:ty, $entity_type:expr, $column_name:expr, $entity_type_table:expr, $entity_permission_table:expr, $effective_permission_table:expr) => {
         impl actix_web::FromRequest for $type {
             type Error = crate::error::APIError;
             type Future =
@@ -126,6 +267,8 @@ macro_rules! impl_permission {
         impl crate::model::permission::EntityPermission for $type {
             type EntityID = $id_type;
 
+            const ENTITY_TYPE: crate::model::permission::EntityType = $entity_type;
+
             fn permission(&self) -> PermissionType {
                 self.permission_type
             }
@@ -163,10 +306,10 @@ macro_rules! impl_permission {
                 let res: Option<(crate::model::permission::PgPermissionType,)> =
                     sqlx::query_as(concat!(
                         "SELECT type FROM ",
-                        $entity_permission_table,
+                        $effective_permission_table,
                         " WHERE ",
                         $column_name,
-                        " = $1 AND account_id = $2"
+                        " = $1 AND account_id = $2 ORDER BY type DESC LIMIT 1"
                     ))
                     .bind(&entity_id)
                     .bind(&account_id)
@@ -199,6 +342,285 @@ macro_rules! impl_permission {
                 .await
                 .map(|_| ())
             }
+
+            async fn list(
+                db: &sqlx::PgPool,
+                entity_id: &Self::EntityID,
+            ) -> sqlx::Result<Vec<crate::model::permission::PermissionHolder>> {
+                use sqlx::postgres::PgQueryAs;
+                sqlx::query_as::<
+                    _,
+                    (
+                        AccountID,
+                        String,
+                        String,
+                        Option<String>,
+                        crate::model::permission::PgPermissionType,
+                    ),
+                >(concat!(
+                    "SELECT Account.id, login, first_name, last_name, type FROM ",
+                    $entity_permission_table,
+                    " JOIN Account ON Account.id = ",
+                    $entity_permission_table,
+                    ".account_id WHERE ",
+                    $column_name,
+                    " = $1 ORDER BY login"
+                ))
+                .bind(entity_id)
+                .fetch_all(db)
+                .await
+                .map(|rows| rows.into_iter().map(Into::into).collect())
+            }
+
+            async fn grant(
+                db: &sqlx::PgPool,
+                permission_type: crate::model::permission::PermissionType,
+                entity_id: &Self::EntityID,
+                account_id: &AccountID,
+            ) -> sqlx::Result<()> {
+                let permission_type: crate::model::permission::PgPermissionType =
+                    permission_type.into();
+                sqlx::query(concat!(
+                    "INSERT INTO ",
+                    $entity_permission_table,
+                    " (type, ",
+                    $column_name,
+                    ", account_id) VALUES ($1, $2, $3) ON CONFLICT (",
+                    $column_name,
+                    ", account_id) DO UPDATE SET type = $1 WHERE ",
+                    $entity_permission_table,
+                    ".type <> 'owner'"
+                ))
+                .bind(permission_type)
+                .bind(entity_id)
+                .bind(account_id)
+                .execute(db)
+                .await
+                .map(|_| ())
+            }
+
+            async fn strengthen_in_transaction(
+                transaction: &mut crate::types::Transaction,
+                permission_type: crate::model::permission::PermissionType,
+                entity_id: &Self::EntityID,
+                account_id: &AccountID,
+            ) -> sqlx::Result<bool> {
+                let permission_type: crate::model::permission::PgPermissionType =
+                    permission_type.into();
+                sqlx::query(concat!(
+                    "INSERT INTO ",
+                    $entity_permission_table,
+                    " (type, ",
+                    $column_name,
+                    ", account_id) VALUES ($1, $2, $3) ON CONFLICT (",
+                    $column_name,
+                    ", account_id) DO UPDATE SET type = $1 WHERE ",
+                    $entity_permission_table,
+                    ".type < $1"
+                ))
+                .bind(permission_type)
+                .bind(entity_id)
+                .bind(account_id)
+                .execute(transaction)
+                .await
+                .map(|affected| affected > 0)
+            }
+
+            async fn revoke(
+                db: &sqlx::PgPool,
+                entity_id: &Self::EntityID,
+                account_id: &AccountID,
+            ) -> sqlx::Result<()> {
+                sqlx::query(concat!(
+                    "DELETE FROM ",
+                    $entity_permission_table,
+                    " WHERE ",
+                    $column_name,
+                    " = $1 AND account_id = $2"
+                ))
+                .bind(entity_id)
+                .bind(account_id)
+                .execute(db)
+                .await
+                .map(|_| ())
+            }
+
+            async fn transfer_ownership(
+                db: &sqlx::PgPool,
+                entity_id: &Self::EntityID,
+                from: &AccountID,
+                to: &AccountID,
+            ) -> sqlx::Result<()> {
+                let mut transaction = db.begin().await?;
+
+                sqlx::query(concat!(
+                    "INSERT INTO ",
+                    $entity_permission_table,
+                    " (type, ",
+                    $column_name,
+                    ", account_id) VALUES ('owner', $1, $2) ON CONFLICT (",
+                    $column_name,
+                    ", account_id) DO UPDATE SET type = 'owner'"
+                ))
+                .bind(entity_id)
+                .bind(to)
+                .execute(&mut transaction)
+                .await?;
+
+                sqlx::query(concat!(
+                    "UPDATE ",
+                    $entity_permission_table,
+                    " SET type = 'rw' WHERE ",
+                    $column_name,
+                    " = $1 AND account_id = $2"
+                ))
+                .bind(entity_id)
+                .bind(from)
+                .execute(&mut transaction)
+                .await?;
+
+                transaction.commit().await?;
+
+                Ok(())
+            }
+
+            async fn release_ownership_in_transaction(
+                transaction: &mut crate::types::Transaction,
+                account_id: &AccountID,
+            ) -> sqlx::Result<()> {
+                sqlx::query(concat!(
+                    "UPDATE ",
+                    $entity_permission_table,
+                    " SET type = 'owner' WHERE (",
+                    $column_name,
+                    ", account_id) IN (SELECT DISTINCT ON (writer.",
+                    $column_name,
+                    ") writer.",
+                    $column_name,
+                    ", writer.account_id FROM ",
+                    $entity_permission_table,
+                    " writer JOIN ",
+                    $entity_permission_table,
+                    " owned ON writer.",
+                    $column_name,
+                    " = owned.",
+                    $column_name,
+                    " WHERE owned.account_id = $1 AND owned.type = 'owner' AND writer.type = 'rw'",
+                    " AND NOT EXISTS (SELECT FROM ",
+                    $entity_permission_table,
+                    " co_owner WHERE co_owner.",
+                    $column_name,
+                    " = owned.",
+                    $column_name,
+                    " AND co_owner.type = 'owner' AND co_owner.account_id <> $1)",
+                    " ORDER BY writer.",
+                    $column_name,
+                    ", writer.account_id)"
+                ))
+                .bind(account_id)
+                .execute(&mut *transaction)
+                .await?;
+
+                sqlx::query(concat!(
+                    "DELETE FROM ",
+                    $entity_type_table,
+                    " WHERE id IN (SELECT ",
+                    $column_name,
+                    " FROM ",
+                    $entity_permission_table,
+                    " owned WHERE owned.account_id = $1 AND owned.type = 'owner'",
+                    " AND NOT EXISTS (SELECT FROM ",
+                    $entity_permission_table,
+                    " co_owner WHERE co_owner.",
+                    $column_name,
+                    " = owned.",
+                    $column_name,
+                    " AND co_owner.type = 'owner' AND co_owner.account_id <> $1))"
+                ))
+                .bind(account_id)
+                .execute(transaction)
+                .await
+                .map(|_| ())
+            }
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! impl_group_permission {
+    ($type:ty, $column_name:expr, $group_permission_table:expr) => {
+        #[async_trait::async_trait]
+        impl crate::model::permission::GroupEntityPermission for $type {
+            async fn list_groups(
+                db: &sqlx::PgPool,
+                entity_id: &<Self as crate::model::permission::EntityPermission>::EntityID,
+            ) -> sqlx::Result<Vec<crate::model::permission::GroupGrant>> {
+                use sqlx::postgres::PgQueryAs;
+                sqlx::query_as::<
+                    _,
+                    (
+                        crate::model::group::GroupID,
+                        String,
+                        crate::model::permission::PgPermissionType,
+                    ),
+                >(concat!(
+                    "SELECT StudentGroup.id, name, type FROM ",
+                    $group_permission_table,
+                    " JOIN StudentGroup ON StudentGroup.id = ",
+                    $group_permission_table,
+                    ".group_id WHERE ",
+                    $column_name,
+                    " = $1 ORDER BY name"
+                ))
+                .bind(entity_id)
+                .fetch_all(db)
+                .await
+                .map(|rows| rows.into_iter().map(Into::into).collect())
+            }
+
+            async fn grant_to_group(
+                db: &sqlx::PgPool,
+                permission_type: crate::model::permission::PermissionType,
+                entity_id: &<Self as crate::model::permission::EntityPermission>::EntityID,
+                group_id: &crate::model::group::GroupID,
+            ) -> sqlx::Result<()> {
+                let permission_type: crate::model::permission::PgPermissionType =
+                    permission_type.into();
+                sqlx::query(concat!(
+                    "INSERT INTO ",
+                    $group_permission_table,
+                    " (type, ",
+                    $column_name,
+                    ", group_id) VALUES ($1, $2, $3) ON CONFLICT (",
+                    $column_name,
+                    ", group_id) DO UPDATE SET type = $1"
+                ))
+                .bind(permission_type)
+                .bind(entity_id)
+                .bind(group_id)
+                .execute(db)
+                .await
+                .map(|_| ())
+            }
+
+            async fn revoke_from_group(
+                db: &sqlx::PgPool,
+                entity_id: &<Self as crate::model::permission::EntityPermission>::EntityID,
+                group_id: &crate::model::group::GroupID,
+            ) -> sqlx::Result<()> {
+                sqlx::query(concat!(
+                    "DELETE FROM ",
+                    $group_permission_table,
+                    " WHERE ",
+                    $column_name,
+                    " = $1 AND group_id = $2"
+                ))
+                .bind(entity_id)
+                .bind(group_id)
+                .execute(db)
+                .await
+                .map(|_| ())
+            }
         }
     };
 }
@@ -237,18 +659,78 @@ impl TeacherPermission {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct TaskPermission {
+    pub permission_type: PermissionType,
+    pub task_id: TaskID,
+    pub account_id: AccountID,
+}
+
+impl TaskPermission {
+    fn new(permission_type: PermissionType, task_id: TaskID, account_id: AccountID) -> Self {
+        Self {
+            permission_type,
+            task_id,
+            account_id,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct GroupPermission {
+    pub permission_type: PermissionType,
+    pub group_id: GroupID,
+    pub account_id: AccountID,
+}
+
+impl GroupPermission {
+    fn new(permission_type: PermissionType, group_id: GroupID, account_id: AccountID) -> Self {
+        Self {
+            permission_type,
+            group_id,
+            account_id,
+        }
+    }
+}
+
 impl_permission!(
     LessonPermission,
     LessonID,
+    EntityType::Lesson,
     "lesson_id",
     "Lesson",
-    "LessonPermission"
+    "LessonPermission",
+    "EffectiveLessonPermission"
 );
 
 impl_permission!(
     TeacherPermission,
     TeacherID,
+    EntityType::Teacher,
     "teacher_id",
     "Teacher",
-    "TeacherPermission"
+    "TeacherPermission",
+    "EffectiveTeacherPermission"
 );
+
+impl_permission!(
+    TaskPermission,
+    TaskID,
+    EntityType::Task,
+    "task_id",
+    "Task",
+    "TaskPermission"
+);
+
+impl_permission!(
+    GroupPermission,
+    GroupID,
+    EntityType::Group,
+    "group_id",
+    "StudentGroup",
+    "GroupPermission"
+);
+
+impl_group_permission!(LessonPermission, "lesson_id", "LessonGroupPermission");
+
+impl_group_permission!(TeacherPermission, "teacher_id", "TeacherGroupPermission");
@@ -1,8 +1,9 @@
-use chrono::{NaiveDate, NaiveTime};
+use chrono::{Duration, NaiveDate, NaiveTime};
 use serde::{Deserialize, Serialize};
 use sqlx::postgres::PgQueryAs;
 use std::vec::Vec;
 
+use super::recurrence::{default_duration, is_within, Recurrence};
 use crate::model::lesson::LessonID;
 use crate::model::templated_insert;
 use crate::types::Transaction;
@@ -14,6 +15,8 @@ pub struct DailyRepeat {
     start_date: NaiveDate,
     #[serde(skip_serializing_if = "Option::is_none")]
     end_date: Option<NaiveDate>,
+    #[serde(default = "default_duration")]
+    duration: i32,
 }
 
 impl DailyRepeat {
@@ -21,7 +24,7 @@ impl DailyRepeat {
         transaction: &mut Transaction,
         lesson_id: &LessonID,
     ) -> sqlx::Result<Vec<DailyRepeat>> {
-        sqlx::query_as("SELECT scheduled_time, start_date, end_date FROM LessonDailyRepeat WHERE lesson_id = $1")
+        sqlx::query_as("SELECT scheduled_time, start_date, end_date, duration FROM LessonDailyRepeat WHERE lesson_id = $1")
             .bind(lesson_id)
             .fetch_all(transaction)
             .await
@@ -34,12 +37,12 @@ impl DailyRepeat {
     ) -> sqlx::Result<()> {
         if !repeats.is_empty() {
             let values = (0..repeats.len())
-                .map(|i| templated_insert(4, i))
+                .map(|i| templated_insert(5, i))
                 .collect::<Vec<String>>()
                 .join(",");
 
             let sql = format!(
-                "INSERT INTO LessonDailyRepeat (scheduled_time, lesson_id, start_date, end_date) VALUES {}",
+                "INSERT INTO LessonDailyRepeat (scheduled_time, lesson_id, start_date, end_date, duration) VALUES {}",
                 values
             );
 
@@ -49,13 +52,15 @@ impl DailyRepeat {
                 scheduled_time,
                 start_date,
                 end_date,
+                duration,
             } in repeats
             {
                 query = query
                     .bind(scheduled_time)
                     .bind(lesson_id)
                     .bind(start_date)
-                    .bind(end_date);
+                    .bind(end_date)
+                    .bind(duration);
             }
             query.execute(transaction).await?;
         }
@@ -82,4 +87,64 @@ impl DailyRepeat {
             .await
             .map(|_| ())
     }
-}
\ No newline at end of file
+}
+
+impl Recurrence for DailyRepeat {
+    fn occurs_on(&self, date: &NaiveDate) -> bool {
+        is_within(date, &self.start_date, &self.end_date)
+    }
+
+    fn starts_at(&self, _: &NaiveDate) -> NaiveTime {
+        self.scheduled_time
+    }
+
+    fn duration(&self) -> Duration {
+        Duration::minutes(self.duration as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::repeat::test_util::date;
+
+    fn repeat(end_date: Option<NaiveDate>) -> DailyRepeat {
+        DailyRepeat {
+            scheduled_time: NaiveTime::from_hms(10, 0, 0),
+            start_date: date(2021, 3, 1),
+            end_date,
+            duration: 45,
+        }
+    }
+
+    #[test]
+    fn occurs_every_day_from_start_date() {
+        let repeat = repeat(None);
+        assert!(!repeat.occurs_on(&date(2021, 2, 28)));
+        assert!(repeat.occurs_on(&date(2021, 3, 1)));
+        assert!(repeat.occurs_on(&date(2021, 3, 2)));
+        assert!(repeat.occurs_on(&date(2022, 3, 1)));
+    }
+
+    #[test]
+    fn end_date_is_inclusive() {
+        let repeat = repeat(Some(date(2021, 3, 3)));
+        assert!(repeat.occurs_on(&date(2021, 3, 3)));
+        assert!(!repeat.occurs_on(&date(2021, 3, 4)));
+    }
+
+    #[test]
+    fn occurrences_between_spans_duration() {
+        let occurrences =
+            repeat(Some(date(2021, 3, 3))).occurrences_between(&date(2021, 2, 27), &date(2021, 3, 5));
+        assert_eq!(
+            occurrences.iter().map(|o| o.starts_at).collect::<Vec<_>>(),
+            vec![
+                date(2021, 3, 1).and_hms(10, 0, 0),
+                date(2021, 3, 2).and_hms(10, 0, 0),
+                date(2021, 3, 3).and_hms(10, 0, 0),
+            ]
+        );
+        assert_eq!(occurrences[0].ends_at, date(2021, 3, 1).and_hms(10, 45, 0));
+    }
+}
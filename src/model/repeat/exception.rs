@@ -0,0 +1,97 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgQueryAs;
+use std::vec::Vec;
+
+use super::recurrence::{TimeSpan, DEFAULT_DURATION};
+use crate::model::lesson::LessonID;
+use crate::model::templated_insert;
+use crate::types::Transaction;
+
+/// Cancels the occurrence of a lesson on `date`, or moves it to `moved_to`
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, sqlx::FromRow)]
+pub struct RepeatException {
+    #[serde(rename = "date")]
+    pub original_date: NaiveDate,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moved_to: Option<NaiveDateTime>,
+    /// Duration of the moved occurrence in minutes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<i32>,
+}
+
+impl RepeatException {
+    pub async fn of_lesson_in_transaction(
+        transaction: &mut Transaction,
+        lesson_id: &LessonID,
+    ) -> sqlx::Result<Vec<RepeatException>> {
+        sqlx::query_as("SELECT original_date, moved_to, duration FROM LessonException WHERE lesson_id = $1")
+            .bind(lesson_id)
+            .fetch_all(transaction)
+            .await
+    }
+
+    pub async fn insert_in_transaction(
+        transaction: &mut Transaction,
+        exceptions: &Vec<RepeatException>,
+        lesson_id: &LessonID,
+    ) -> sqlx::Result<()> {
+        if !exceptions.is_empty() {
+            let values = (0..exceptions.len())
+                .map(|i| templated_insert(4, i))
+                .collect::<Vec<String>>()
+                .join(",");
+
+            let sql = format!(
+                "INSERT INTO LessonException (original_date, moved_to, duration, lesson_id) VALUES {}",
+                values
+            );
+
+            let mut query = sqlx::query(&sql[..]);
+
+            for RepeatException {
+                original_date,
+                moved_to,
+                duration,
+            } in exceptions
+            {
+                query = query
+                    .bind(original_date)
+                    .bind(moved_to)
+                    .bind(duration)
+                    .bind(lesson_id);
+            }
+            query.execute(transaction).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn update_in_transaction(
+        transaction: &mut Transaction,
+        exceptions: &Vec<RepeatException>,
+        lesson_id: &LessonID,
+    ) -> sqlx::Result<()> {
+        RepeatException::delete_in_transaction(transaction, lesson_id).await?;
+        RepeatException::insert_in_transaction(transaction, exceptions, lesson_id).await
+    }
+
+    pub async fn delete_in_transaction(
+        transaction: &mut Transaction,
+        lesson_id: &LessonID,
+    ) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM LessonException WHERE lesson_id = $1")
+            .bind(lesson_id)
+            .execute(transaction)
+            .await
+            .map(|_| ())
+    }
+
+    pub fn moved_span(&self) -> Option<TimeSpan> {
+        self.moved_to.map(|starts_at| TimeSpan {
+            starts_at,
+            ends_at: starts_at
+                + Duration::minutes(self.duration.unwrap_or(DEFAULT_DURATION) as i64),
+        })
+    }
+}
@@ -2,8 +2,21 @@ pub mod weekly;
 pub mod daily;
 pub mod monthly;
 pub mod single_occurrence;
+pub mod exception;
+pub mod recurrence;
 
 pub use weekly::*;
 pub use daily::*;
 pub use monthly::*;
 pub use single_occurrence::*;
+pub use exception::*;
+pub use recurrence::{Recurrence, TimeSpan, DEFAULT_DURATION};
+
+#[cfg(test)]
+pub mod test_util {
+    use chrono::NaiveDate;
+
+    pub fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(year, month, day)
+    }
+}
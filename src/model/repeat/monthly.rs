@@ -1,8 +1,9 @@
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime};
 use serde::{Deserialize, Serialize};
 use sqlx::postgres::PgQueryAs;
 use std::vec::Vec;
 
+use super::recurrence::{default_duration, is_within, month_difference, Recurrence};
 use crate::model::lesson::LessonID;
 use crate::model::templated_insert;
 use crate::types::Transaction;
@@ -15,6 +16,8 @@ pub struct MonthlyRepeat {
     start_date: NaiveDate,
     #[serde(skip_serializing_if = "Option::is_none")]
     end_date: Option<NaiveDate>,
+    #[serde(default = "default_duration")]
+    duration: i32,
 }
 
 impl MonthlyRepeat {
@@ -22,7 +25,7 @@ impl MonthlyRepeat {
         transaction: &mut Transaction,
         lesson_id: &LessonID,
     ) -> sqlx::Result<Vec<MonthlyRepeat>> {
-        sqlx::query_as("SELECT every, scheduled_time, start_date, end_date FROM LessonMonthlyRepeat WHERE lesson_id = $1")
+        sqlx::query_as("SELECT every, scheduled_time, start_date, end_date, duration FROM LessonMonthlyRepeat WHERE lesson_id = $1")
             .bind(lesson_id)
             .fetch_all(transaction)
             .await
@@ -35,12 +38,12 @@ impl MonthlyRepeat {
     ) -> sqlx::Result<()> {
         if !repeats.is_empty() {
             let values = (0..repeats.len())
-                .map(|i| templated_insert(5, i))
+                .map(|i| templated_insert(6, i))
                 .collect::<Vec<String>>()
                 .join(",");
 
             let sql = format!(
-                "INSERT INTO LessonMonthlyRepeat (every, scheduled_time, lesson_id, start_date, end_date) VALUES {}",
+                "INSERT INTO LessonMonthlyRepeat (every, scheduled_time, lesson_id, start_date, end_date, duration) VALUES {}",
                 values
             );
 
@@ -51,6 +54,7 @@ impl MonthlyRepeat {
                 scheduled_time,
                 start_date,
                 end_date,
+                duration,
             } in repeats
             {
                 query = query
@@ -58,7 +62,8 @@ impl MonthlyRepeat {
                     .bind(scheduled_time)
                     .bind(lesson_id)
                     .bind(start_date)
-                    .bind(end_date);
+                    .bind(end_date)
+                    .bind(duration);
             }
             query.execute(transaction).await?;
         }
@@ -86,3 +91,74 @@ impl MonthlyRepeat {
             .map(|_| ())
     }
 }
+
+impl Recurrence for MonthlyRepeat {
+    fn occurs_on(&self, date: &NaiveDate) -> bool {
+        let scheduled_date = self.scheduled_time.date();
+        is_within(date, &self.start_date, &self.end_date)
+            && date.day() == scheduled_date.day()
+            && month_difference(date, &scheduled_date) % self.every == 0
+    }
+
+    fn starts_at(&self, _: &NaiveDate) -> NaiveTime {
+        self.scheduled_time.time()
+    }
+
+    fn duration(&self) -> Duration {
+        Duration::minutes(self.duration as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::repeat::test_util::date;
+
+    fn repeat(every: i32, scheduled_date: NaiveDate, end_date: Option<NaiveDate>) -> MonthlyRepeat {
+        MonthlyRepeat {
+            every,
+            scheduled_time: scheduled_date.and_hms(12, 0, 0),
+            start_date: date(2021, 1, 1),
+            end_date,
+            duration: 90,
+        }
+    }
+
+    #[test]
+    fn occurs_on_scheduled_day_of_month() {
+        let repeat = repeat(1, date(2021, 1, 15), None);
+        assert!(repeat.occurs_on(&date(2021, 1, 15)));
+        assert!(repeat.occurs_on(&date(2021, 2, 15)));
+        assert!(!repeat.occurs_on(&date(2021, 2, 16)));
+    }
+
+    #[test]
+    fn skips_months_without_scheduled_day() {
+        let repeat = repeat(1, date(2021, 1, 31), None);
+        assert!(!repeat.occurs_on(&date(2021, 2, 28)));
+        assert!(repeat.occurs_on(&date(2021, 3, 31)));
+        assert!(!repeat.occurs_on(&date(2021, 4, 30)));
+    }
+
+    #[test]
+    fn steps_months_from_scheduled_date() {
+        let repeat = repeat(2, date(2021, 5, 15), None);
+        assert!(repeat.occurs_on(&date(2021, 3, 15)));
+        assert!(!repeat.occurs_on(&date(2021, 4, 15)));
+        assert!(repeat.occurs_on(&date(2021, 7, 15)));
+        assert!(repeat.occurs_on(&date(2022, 1, 15)));
+    }
+
+    #[test]
+    fn does_not_occur_before_start_date() {
+        let repeat = repeat(1, date(2021, 1, 15), None);
+        assert!(!repeat.occurs_on(&date(2020, 12, 15)));
+    }
+
+    #[test]
+    fn end_date_is_inclusive() {
+        let repeat = repeat(1, date(2021, 1, 15), Some(date(2021, 3, 15)));
+        assert!(repeat.occurs_on(&date(2021, 3, 15)));
+        assert!(!repeat.occurs_on(&date(2021, 4, 15)));
+    }
+}
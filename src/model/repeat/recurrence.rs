@@ -0,0 +1,90 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::Serialize;
+use std::vec::Vec;
+
+/// Duration in minutes used when none is specified
+pub const DEFAULT_DURATION: i32 = 90;
+
+pub fn default_duration() -> i32 {
+    DEFAULT_DURATION
+}
+
+#[derive(Serialize, Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct TimeSpan {
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+}
+
+/// Schedule rule that can be evaluated without a round-trip to the database.
+///
+/// Implementations follow the semantics of the `repeats_on_date_*` SQL functions.
+pub trait Recurrence {
+    fn occurs_on(&self, date: &NaiveDate) -> bool;
+
+    /// Time of day at which the occurrence starts
+    fn starts_at(&self, date: &NaiveDate) -> NaiveTime;
+
+    fn duration(&self) -> Duration;
+
+    fn span_on(&self, date: &NaiveDate) -> TimeSpan {
+        let starts_at = date.and_time(self.starts_at(date));
+        TimeSpan {
+            starts_at,
+            ends_at: starts_at + self.duration(),
+        }
+    }
+
+    fn occurrences_between(&self, from: &NaiveDate, to: &NaiveDate) -> Vec<TimeSpan> {
+        let mut res = Vec::new();
+        let mut date = *from;
+        while date <= *to {
+            if self.occurs_on(&date) {
+                res.push(self.span_on(&date));
+            }
+            date = date.succ();
+        }
+        res
+    }
+}
+
+/// Whole months between `a` and `b`, ignoring days. Mirrors `datediff_month`
+pub fn month_difference(a: &NaiveDate, b: &NaiveDate) -> i32 {
+    (a.year() - b.year()) * 12 + (a.month() as i32 - b.month() as i32)
+}
+
+pub fn is_within(date: &NaiveDate, start_date: &NaiveDate, end_date: &Option<NaiveDate>) -> bool {
+    *start_date <= *date && end_date.map_or(true, |end_date| end_date >= *date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::repeat::test_util::date;
+
+    #[test]
+    fn month_difference_ignores_days() {
+        assert_eq!(month_difference(&date(2021, 3, 1), &date(2021, 1, 31)), 2);
+        assert_eq!(month_difference(&date(2021, 3, 31), &date(2021, 3, 1)), 0);
+    }
+
+    #[test]
+    fn month_difference_crosses_years() {
+        assert_eq!(month_difference(&date(2021, 1, 15), &date(2020, 11, 15)), 2);
+        assert_eq!(month_difference(&date(2020, 11, 15), &date(2021, 1, 15)), -2);
+    }
+
+    #[test]
+    fn is_within_includes_both_ends() {
+        let start = date(2021, 3, 1);
+        let end = Some(date(2021, 3, 10));
+        assert!(!is_within(&date(2021, 2, 28), &start, &end));
+        assert!(is_within(&start, &start, &end));
+        assert!(is_within(&date(2021, 3, 10), &start, &end));
+        assert!(!is_within(&date(2021, 3, 11), &start, &end));
+    }
+
+    #[test]
+    fn is_within_without_end_date() {
+        assert!(is_within(&date(2030, 1, 1), &date(2021, 3, 1), &None));
+    }
+}
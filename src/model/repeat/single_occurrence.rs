@@ -1,27 +1,30 @@
-use chrono::NaiveDateTime;
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
 use serde::{Deserialize, Serialize};
 use sqlx::postgres::PgQueryAs;
 use std::vec::Vec;
 
+use super::recurrence::{default_duration, Recurrence};
 use crate::model::lesson::LessonID;
+use crate::model::templated_insert;
 use crate::types::Transaction;
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, sqlx::Type)]
-#[sqlx(transparent)]
-pub struct SingleOccurrence(NaiveDateTime);
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, sqlx::FromRow)]
+pub struct SingleOccurrence {
+    #[serde(rename = "at")]
+    occurs_at: NaiveDateTime,
+    #[serde(default = "default_duration")]
+    duration: i32,
+}
 
 impl SingleOccurrence {
     pub async fn of_lesson_in_transaction(
         transaction: &mut Transaction,
         lesson_id: &LessonID,
     ) -> sqlx::Result<Vec<SingleOccurrence>> {
-        sqlx::query_as::<_, (SingleOccurrence,)>(
-            "SELECT occurs_at FROM SingleOccurrence WHERE lesson_id = $1",
-        )
-        .bind(lesson_id)
-        .fetch_all(transaction)
-        .await
-        .map(|vec| vec.into_iter().map(|(occurrence,)| occurrence).collect())
+        sqlx::query_as("SELECT occurs_at, duration FROM SingleOccurrence WHERE lesson_id = $1")
+            .bind(lesson_id)
+            .fetch_all(transaction)
+            .await
     }
 
     pub async fn insert_in_transaction(
@@ -31,19 +34,23 @@ impl SingleOccurrence {
     ) -> sqlx::Result<()> {
         if !singles.is_empty() {
             let values = (0..singles.len())
-                .map(|i| format!("(${}, ${})", i * 2 + 1, i * 2 + 2,))
+                .map(|i| templated_insert(3, i))
                 .collect::<Vec<String>>()
                 .join(",");
 
             let sql = format!(
-                "INSERT INTO SingleOccurrence (occurs_at, lesson_id) VALUES {}",
+                "INSERT INTO SingleOccurrence (occurs_at, duration, lesson_id) VALUES {}",
                 values
             );
 
             let mut query = sqlx::query(&sql[..]);
 
-            for SingleOccurrence(occurrence) in singles {
-                query = query.bind(occurrence).bind(lesson_id);
+            for SingleOccurrence {
+                occurs_at,
+                duration,
+            } in singles
+            {
+                query = query.bind(occurs_at).bind(duration).bind(lesson_id);
             }
             query.execute(transaction).await?;
         }
@@ -72,3 +79,44 @@ impl SingleOccurrence {
     }
 
 }
+
+impl Recurrence for SingleOccurrence {
+    fn occurs_on(&self, date: &NaiveDate) -> bool {
+        self.occurs_at.date() == *date
+    }
+
+    fn starts_at(&self, _: &NaiveDate) -> NaiveTime {
+        self.occurs_at.time()
+    }
+
+    fn duration(&self) -> Duration {
+        Duration::minutes(self.duration as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn single(occurs_at: NaiveDateTime) -> SingleOccurrence {
+        SingleOccurrence {
+            occurs_at,
+            duration: 90,
+        }
+    }
+
+    #[test]
+    fn occurs_on_its_date_only() {
+        let single = single(NaiveDate::from_ymd(2021, 3, 3).and_hms(9, 0, 0));
+        assert!(single.occurs_on(&NaiveDate::from_ymd(2021, 3, 3)));
+        assert!(!single.occurs_on(&NaiveDate::from_ymd(2021, 3, 4)));
+    }
+
+    #[test]
+    fn span_may_end_on_next_day() {
+        let date = NaiveDate::from_ymd(2021, 3, 3);
+        let span = single(date.and_hms(23, 0, 0)).span_on(&date);
+        assert_eq!(span.starts_at, date.and_hms(23, 0, 0));
+        assert_eq!(span.ends_at, NaiveDate::from_ymd(2021, 3, 4).and_hms(0, 30, 0));
+    }
+}
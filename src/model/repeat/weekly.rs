@@ -1,9 +1,10 @@
-use chrono::{NaiveDate, NaiveTime};
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime};
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use sqlx::postgres::PgQueryAs;
 use std::vec::Vec;
 
+use super::recurrence::{default_duration, is_within, Recurrence};
 use crate::model::lesson::LessonID;
 use crate::model::templated_insert;
 use crate::types::Transaction;
@@ -30,6 +31,8 @@ pub struct WeeklyRepeat {
     start_date: NaiveDate,
     #[serde(skip_serializing_if = "Option::is_none")]
     end_date: Option<NaiveDate>,
+    #[serde(default = "default_duration")]
+    duration: i32,
 }
 
 impl WeeklyRepeat {
@@ -37,7 +40,7 @@ impl WeeklyRepeat {
         transaction: &mut Transaction,
         lesson_id: &LessonID,
     ) -> sqlx::Result<Vec<WeeklyRepeat>> {
-        sqlx::query_as("SELECT every, week_day, scheduled_time, start_date, end_date FROM LessonWeeklyRepeat WHERE lesson_id = $1")
+        sqlx::query_as("SELECT every, week_day, scheduled_time, start_date, end_date, duration FROM LessonWeeklyRepeat WHERE lesson_id = $1")
             .bind(lesson_id)
             .fetch_all(transaction)
             .await
@@ -50,12 +53,12 @@ impl WeeklyRepeat {
     ) -> sqlx::Result<()> {
         if !repeats.is_empty() {
             let values = (0..repeats.len())
-                .map(|i| templated_insert(6, i))
+                .map(|i| templated_insert(7, i))
                 .collect::<Vec<String>>()
                 .join(",");
 
             let sql = format!(
-                "INSERT INTO LessonWeeklyRepeat (every, week_day, scheduled_time, lesson_id, start_date, end_date) VALUES {}",
+                "INSERT INTO LessonWeeklyRepeat (every, week_day, scheduled_time, lesson_id, start_date, end_date, duration) VALUES {}",
                 values
             );
 
@@ -67,6 +70,7 @@ impl WeeklyRepeat {
                 scheduled_time,
                 start_date,
                 end_date,
+                duration,
             } in repeats
             {
                 query = query
@@ -75,7 +79,8 @@ impl WeeklyRepeat {
                     .bind(scheduled_time)
                     .bind(lesson_id)
                     .bind(start_date)
-                    .bind(end_date);
+                    .bind(end_date)
+                    .bind(duration);
             }
             query.execute(transaction).await?;
         }
@@ -104,3 +109,85 @@ impl WeeklyRepeat {
     }
 
 }
+
+impl WeeklyRepeat {
+    /// First date on or after `start_date` which falls on `week_day`
+    fn first_occurrence(&self) -> NaiveDate {
+        let week_day = self.week_day as i64;
+        let start_day = self.start_date.weekday().number_from_monday() as i64;
+        self.start_date + Duration::days((week_day - start_day + 7) % 7)
+    }
+}
+
+impl Recurrence for WeeklyRepeat {
+    fn occurs_on(&self, date: &NaiveDate) -> bool {
+        is_within(date, &self.start_date, &self.end_date)
+            && self.week_day as u32 == date.weekday().number_from_monday()
+            && (*date - self.first_occurrence()).num_days() % self.every as i64 == 0
+    }
+
+    fn starts_at(&self, _: &NaiveDate) -> NaiveTime {
+        self.scheduled_time
+    }
+
+    fn duration(&self) -> Duration {
+        Duration::minutes(self.duration as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::repeat::test_util::date;
+
+    /// Wednesdays, starting with the week of Monday 2021-03-01
+    fn repeat(every: i32, end_date: Option<NaiveDate>) -> WeeklyRepeat {
+        WeeklyRepeat {
+            every,
+            week_day: WeekDay::Wednesday,
+            scheduled_time: NaiveTime::from_hms(8, 30, 0),
+            start_date: date(2021, 3, 1),
+            end_date,
+            duration: 90,
+        }
+    }
+
+    #[test]
+    fn occurs_on_week_day_only() {
+        let repeat = repeat(7, None);
+        assert!(repeat.occurs_on(&date(2021, 3, 3)));
+        assert!(!repeat.occurs_on(&date(2021, 3, 4)));
+        assert!(repeat.occurs_on(&date(2021, 3, 10)));
+    }
+
+    #[test]
+    fn every_is_measured_in_days() {
+        // Week days are seven days apart, so both 1 and 7 repeat weekly
+        for every in &[1, 7] {
+            let repeat = repeat(*every, None);
+            assert!(repeat.occurs_on(&date(2021, 3, 3)));
+            assert!(repeat.occurs_on(&date(2021, 3, 10)));
+        }
+
+        let biweekly = repeat(14, None);
+        assert!(biweekly.occurs_on(&date(2021, 3, 3)));
+        assert!(!biweekly.occurs_on(&date(2021, 3, 10)));
+        assert!(biweekly.occurs_on(&date(2021, 3, 17)));
+    }
+
+    #[test]
+    fn counts_from_first_week_day_after_start_date() {
+        let mut repeat = repeat(14, None);
+        repeat.start_date = date(2021, 3, 4);
+        assert!(!repeat.occurs_on(&date(2021, 3, 3)));
+        assert!(repeat.occurs_on(&date(2021, 3, 10)));
+        assert!(!repeat.occurs_on(&date(2021, 3, 17)));
+    }
+
+    #[test]
+    fn end_date_is_inclusive() {
+        let repeat = repeat(7, Some(date(2021, 3, 10)));
+        assert!(repeat.occurs_on(&date(2021, 3, 10)));
+        assert!(!repeat.occurs_on(&date(2021, 3, 17)));
+    }
+}
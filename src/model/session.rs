@@ -0,0 +1,166 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use indoc::indoc;
+use serde::Serialize;
+use sqlx::postgres::{PgPool, PgQueryAs};
+
+use super::account::AccountID;
+use crate::token::{ApplicationToken, RefreshToken, RefreshTokenID, SecondsSinceEpoch};
+use crate::uuid_wrapper;
+
+uuid_wrapper!(SessionID);
+
+/// Time during which the refresh token replaced by a rotation may still be presented without
+/// counting as reuse, concurrent refreshes of one client race for the same token
+pub fn rotation_grace_period() -> Duration {
+    Duration::seconds(10)
+}
+
+/// Device logged into an account. Every refresh moves the session to the newly issued refresh token
+#[derive(Serialize, Clone, Debug, sqlx::FromRow)]
+pub struct Session {
+    pub id: SessionID,
+    /// Refresh token currently representing the session
+    #[serde(skip)]
+    pub token_id: RefreshTokenID,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    pub created_at: NaiveDateTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_refreshed_at: Option<NaiveDateTime>,
+}
+
+impl Session {
+    /// Expiration of the refresh token currently representing the session
+    pub fn expires_at(&self) -> SecondsSinceEpoch {
+        let issued_at = self.last_refreshed_at.unwrap_or(self.created_at);
+        issued_at.timestamp() + RefreshToken::valid_for().num_seconds()
+    }
+
+    pub async fn create(
+        db: &PgPool,
+        account_id: &AccountID,
+        token_id: &RefreshTokenID,
+        user_agent: Option<String>,
+        ip: Option<String>,
+    ) -> sqlx::Result<SessionID> {
+        let (id,): (SessionID,) = sqlx::query_as(indoc! {"
+            INSERT INTO Session (account_id, token_id, user_agent, ip) VALUES ($1, $2, $3, $4)
+            RETURNING id
+        "})
+        .bind(account_id)
+        .bind(token_id)
+        .bind(user_agent)
+        .bind(ip)
+        .fetch_one(db)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Moves the session from the old refresh token to the new one.
+    /// Returns `false` when the session is gone or is no longer represented by the old token
+    pub async fn rotate(
+        db: &PgPool,
+        session_id: &SessionID,
+        old_token_id: &RefreshTokenID,
+        new_token_id: &RefreshTokenID,
+        user_agent: Option<String>,
+        ip: Option<String>,
+    ) -> sqlx::Result<bool> {
+        sqlx::query(indoc! {"
+            UPDATE Session
+            SET token_id = $2,
+                previous_token_id = token_id,
+                user_agent = coalesce($3, user_agent),
+                ip = coalesce($4, ip),
+                last_refreshed_at = (now() AT TIME ZONE 'utc')
+            WHERE id = $5 AND token_id = $1
+        "})
+        .bind(old_token_id)
+        .bind(new_token_id)
+        .bind(user_agent)
+        .bind(ip)
+        .bind(session_id)
+        .execute(db)
+        .await
+        .map(|affected| affected > 0)
+    }
+
+    /// Whether the token was replaced by the last rotation of the session within the grace period
+    pub async fn rotated_recently_from(
+        db: &PgPool,
+        session_id: &SessionID,
+        token_id: &RefreshTokenID,
+    ) -> sqlx::Result<bool> {
+        let since = Utc::now().naive_utc() - rotation_grace_period();
+        let (recent,): (bool,) = sqlx::query_as(indoc! {"
+            SELECT EXISTS (
+                SELECT 1 FROM Session
+                WHERE id = $1 AND previous_token_id = $2 AND last_refreshed_at > $3
+            )
+        "})
+        .bind(session_id)
+        .bind(token_id)
+        .bind(since)
+        .fetch_one(db)
+        .await?;
+
+        Ok(recent)
+    }
+
+    /// Sessions whose refresh token has not expired yet
+    pub async fn of_account(db: &PgPool, account_id: &AccountID) -> sqlx::Result<Vec<Session>> {
+        let issued_after = Utc::now().naive_utc() - RefreshToken::valid_for();
+        sqlx::query_as(indoc! {"
+            SELECT id, token_id, user_agent, ip, created_at, last_refreshed_at
+            FROM Session
+            WHERE account_id = $1 AND coalesce(last_refreshed_at, created_at) > $2
+            ORDER BY coalesce(last_refreshed_at, created_at) DESC
+        "})
+        .bind(account_id)
+        .bind(issued_after)
+        .fetch_all(db)
+        .await
+    }
+
+    /// Returns the deleted session, nothing if it does not exist or belongs to another account
+    pub async fn delete(
+        db: &PgPool,
+        session_id: &SessionID,
+        account_id: &AccountID,
+    ) -> sqlx::Result<Option<Session>> {
+        sqlx::query_as(indoc! {"
+            DELETE FROM Session WHERE id = $1 AND account_id = $2
+            RETURNING id, token_id, user_agent, ip, created_at, last_refreshed_at
+        "})
+        .bind(session_id)
+        .bind(account_id)
+        .fetch_optional(db)
+        .await
+    }
+
+    /// Deletes the session the token represents or was replaced in by the last rotation.
+    /// Returns the deleted session, if any
+    pub async fn delete_by_token(
+        db: &PgPool,
+        token_id: &RefreshTokenID,
+    ) -> sqlx::Result<Option<Session>> {
+        sqlx::query_as(indoc! {"
+            DELETE FROM Session WHERE token_id = $1 OR previous_token_id = $1
+            RETURNING id, token_id, user_agent, ip, created_at, last_refreshed_at
+        "})
+        .bind(token_id)
+        .fetch_optional(db)
+        .await
+    }
+
+    pub async fn delete_all_of_account(db: &PgPool, account_id: &AccountID) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM Session WHERE account_id = $1")
+            .bind(account_id)
+            .execute(db)
+            .await
+            .map(|_| ())
+    }
+}
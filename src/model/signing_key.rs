@@ -0,0 +1,101 @@
+use chrono::NaiveDateTime;
+use indoc::indoc;
+use serde::Serialize;
+use sqlx::postgres::{PgPool, PgQueryAs};
+
+#[derive(Serialize, Clone, Debug, sqlx::FromRow)]
+pub struct StoredKey {
+    pub kid: String,
+    /// PEM encoded private key sealed with the key encryption key
+    #[serde(skip)]
+    pub sealed_private_key: Vec<u8>,
+    pub created_at: NaiveDateTime,
+    /// Keys are published before they start signing tokens
+    pub activates_at: NaiveDateTime,
+    /// Retired keys only verify tokens signed before the retirement
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retired_at: Option<NaiveDateTime>,
+}
+
+impl StoredKey {
+    /// Keys not retired before `retired_after`, the one activated last first
+    pub async fn valid(db: &PgPool, retired_after: &NaiveDateTime) -> sqlx::Result<Vec<StoredKey>> {
+        sqlx::query_as(indoc! {"
+            SELECT kid, sealed_private_key, created_at, activates_at, retired_at
+            FROM SigningKey
+            WHERE (retired_at IS NULL OR retired_at > $1) AND sealed_private_key IS NOT NULL
+            ORDER BY activates_at DESC
+        "})
+        .bind(retired_after)
+        .fetch_all(db)
+        .await
+    }
+
+    /// Kids and PEM encoded private keys stored before keys were sealed
+    pub async fn unsealed(db: &PgPool) -> sqlx::Result<Vec<(String, String)>> {
+        sqlx::query_as("SELECT kid, private_key FROM SigningKey WHERE sealed_private_key IS NULL")
+            .fetch_all(db)
+            .await
+    }
+
+    /// Replaces the plaintext private key with the sealed one
+    pub async fn seal(db: &PgPool, kid: &str, sealed_private_key: &[u8]) -> sqlx::Result<()> {
+        sqlx::query(
+            "UPDATE SigningKey SET sealed_private_key = $2, private_key = NULL WHERE kid = $1",
+        )
+        .bind(kid)
+        .bind(sealed_private_key)
+        .execute(db)
+        .await
+        .map(|_| ())
+    }
+
+    /// Makes the key the current one unless there already is one
+    pub async fn seed(db: &PgPool, kid: &str, sealed_private_key: &[u8]) -> sqlx::Result<()> {
+        sqlx::query(indoc! {"
+            INSERT INTO SigningKey (kid, sealed_private_key) VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+        "})
+        .bind(kid)
+        .bind(sealed_private_key)
+        .execute(db)
+        .await
+        .map(|_| ())
+    }
+
+    /// Makes the key the current one at `activates_at`, retiring the previous at the same time
+    pub async fn rotate(
+        db: &PgPool,
+        kid: &str,
+        sealed_private_key: &[u8],
+        activates_at: &NaiveDateTime,
+    ) -> sqlx::Result<()> {
+        let mut transaction = db.begin().await?;
+
+        sqlx::query("UPDATE SigningKey SET retired_at = $1 WHERE retired_at IS NULL")
+            .bind(activates_at)
+            .execute(&mut transaction)
+            .await?;
+
+        sqlx::query(indoc! {"
+            INSERT INTO SigningKey (kid, sealed_private_key, activates_at) VALUES ($1, $2, $3)
+        "})
+        .bind(kid)
+        .bind(sealed_private_key)
+        .bind(activates_at)
+        .execute(&mut transaction)
+        .await?;
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Drops keys retired before `retired_before`, no token they signed is alive anymore
+    pub async fn purge(db: &PgPool, retired_before: &NaiveDateTime) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM SigningKey WHERE retired_at <= $1")
+            .bind(retired_before)
+            .execute(db)
+            .await
+            .map(|_| ())
+    }
+}
@@ -0,0 +1,259 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use indoc::indoc;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgPool, PgQueryAs};
+
+use super::account::AccountID;
+use super::lesson::LessonID;
+use super::permission::{EntityPermission, PermissionType, TaskPermission};
+use crate::uuid_wrapper;
+
+uuid_wrapper!(TaskID);
+
+#[derive(Serialize, Deserialize, Clone, Debug, sqlx::FromRow)]
+pub struct Task {
+    pub id: TaskID,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lesson_id: Option<LessonID>,
+    pub lesson_date: NaiveDate,
+    /// Completion time for the account the task was fetched for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Clone, Debug, sqlx::FromRow)]
+pub struct TaskCompletion {
+    pub task_id: TaskID,
+    pub completed_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Clone, Debug, sqlx::FromRow)]
+pub struct TaskProgress {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lesson_id: Option<LessonID>,
+    pub completed: i64,
+    pub open: i64,
+}
+
+impl Task {
+    pub async fn by_id(
+        db: &PgPool,
+        task_id: TaskID,
+        account_id: &AccountID,
+    ) -> sqlx::Result<Option<Task>> {
+        sqlx::query_as(indoc! {"
+            SELECT id, name, description, lesson_id, lesson_date, completed_at
+            FROM Task
+            LEFT JOIN TaskCompletion
+                ON Task.id = TaskCompletion.task_id AND TaskCompletion.account_id = $2
+            WHERE id = $1
+        "})
+        .bind(task_id)
+        .bind(account_id)
+        .fetch_optional(db)
+        .await
+    }
+
+    pub async fn create(
+        db: &PgPool,
+        name: String,
+        description: Option<String>,
+        lesson_id: Option<LessonID>,
+        lesson_date: NaiveDate,
+        owner: &AccountID,
+    ) -> sqlx::Result<Task> {
+        let mut transaction = db.begin().await?;
+
+        let (id,): (TaskID,) = sqlx::query_as(
+            "INSERT INTO Task (name, description, lesson_id, lesson_date) VALUES ($1, $2, $3, $4) RETURNING id",
+        )
+        .bind(&name)
+        .bind(&description)
+        .bind(&lesson_id)
+        .bind(&lesson_date)
+        .fetch_one(&mut transaction)
+        .await?;
+
+        TaskPermission::save_in_transaction(
+            &mut transaction,
+            PermissionType::Owner,
+            &id,
+            owner,
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(Task {
+            id,
+            name,
+            description,
+            lesson_id,
+            lesson_date,
+            completed_at: None,
+        })
+    }
+
+    pub async fn update(
+        db: &PgPool,
+        task_id: &TaskID,
+        name: Option<String>,
+        description: Option<Option<String>>,
+        lesson_id: Option<Option<LessonID>>,
+        lesson_date: Option<NaiveDate>,
+    ) -> sqlx::Result<()> {
+        let mut parts = Vec::<String>::with_capacity(4);
+        let mut counter = 0_u8;
+
+        if name.is_some() {
+            counter += 1;
+            parts.push(format!("name = ${}", counter));
+        }
+
+        if description.is_some() {
+            counter += 1;
+            parts.push(format!("description = ${}", counter));
+        }
+
+        if lesson_id.is_some() {
+            counter += 1;
+            parts.push(format!("lesson_id = ${}", counter));
+        }
+
+        if lesson_date.is_some() {
+            counter += 1;
+            parts.push(format!("lesson_date = ${}", counter));
+        }
+
+        if counter == 0 {
+            return Ok(());
+        }
+
+        let sql = format!(
+            "UPDATE Task SET {} WHERE id = ${}",
+            parts.join(","),
+            counter + 1
+        );
+        let mut query = sqlx::query(&sql[..]);
+
+        if let Some(name) = name {
+            query = query.bind(name);
+        }
+
+        if let Some(description) = description {
+            query = query.bind(description);
+        }
+
+        if let Some(lesson_id) = lesson_id {
+            query = query.bind(lesson_id);
+        }
+
+        if let Some(lesson_date) = lesson_date {
+            query = query.bind(lesson_date);
+        }
+
+        query.bind(task_id).execute(db).await.map(|_| ())
+    }
+
+    pub async fn delete(db: &PgPool, task_id: &TaskID) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM Task WHERE id = $1")
+            .bind(task_id)
+            .execute(db)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn for_date_range(
+        db: &PgPool,
+        from: &NaiveDate,
+        to: &NaiveDate,
+        account_id: &AccountID,
+    ) -> sqlx::Result<Vec<Task>> {
+        sqlx::query_as(indoc! {"
+            SELECT id, name, description, lesson_id, lesson_date, completed_at
+            FROM Task
+            JOIN TaskPermission ON Task.id = TaskPermission.task_id
+            LEFT JOIN TaskCompletion
+                ON Task.id = TaskCompletion.task_id AND TaskCompletion.account_id = $3
+            WHERE TaskPermission.account_id = $3 AND lesson_date BETWEEN $1 AND $2
+            ORDER BY lesson_date
+        "})
+        .bind(from)
+        .bind(to)
+        .bind(account_id)
+        .fetch_all(db)
+        .await
+    }
+
+    pub async fn complete(
+        db: &PgPool,
+        task_id: &TaskID,
+        account_id: &AccountID,
+    ) -> sqlx::Result<TaskCompletion> {
+        let mut transaction = db.begin().await?;
+
+        sqlx::query(indoc! {"
+            INSERT INTO TaskCompletion (task_id, account_id) VALUES ($1, $2)
+            ON CONFLICT ON CONSTRAINT taskcompletion_unique_connection DO NOTHING
+        "})
+        .bind(task_id)
+        .bind(account_id)
+        .execute(&mut transaction)
+        .await?;
+
+        let completion = sqlx::query_as(
+            "SELECT task_id, completed_at FROM TaskCompletion WHERE task_id = $1 AND account_id = $2",
+        )
+        .bind(task_id)
+        .bind(account_id)
+        .fetch_one(&mut transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(completion)
+    }
+
+    pub async fn uncomplete(
+        db: &PgPool,
+        task_id: &TaskID,
+        account_id: &AccountID,
+    ) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM TaskCompletion WHERE task_id = $1 AND account_id = $2")
+            .bind(task_id)
+            .bind(account_id)
+            .execute(db)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn progress(
+        db: &PgPool,
+        from: &Option<NaiveDate>,
+        to: &Option<NaiveDate>,
+        account_id: &AccountID,
+    ) -> sqlx::Result<Vec<TaskProgress>> {
+        sqlx::query_as(indoc! {"
+            SELECT
+                lesson_id,
+                count(TaskCompletion.task_id) AS completed,
+                count(*) - count(TaskCompletion.task_id) AS open
+            FROM Task
+            JOIN TaskPermission ON Task.id = TaskPermission.task_id
+            LEFT JOIN TaskCompletion
+                ON Task.id = TaskCompletion.task_id AND TaskCompletion.account_id = $3
+            WHERE TaskPermission.account_id = $3
+                AND ($1::DATE IS NULL OR lesson_date >= $1)
+                AND ($2::DATE IS NULL OR lesson_date <= $2)
+            GROUP BY lesson_id
+        "})
+        .bind(from)
+        .bind(to)
+        .bind(account_id)
+        .fetch_all(db)
+        .await
+    }
+}
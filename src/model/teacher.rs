@@ -1,3 +1,4 @@
+use chrono::NaiveDateTime;
 use indoc::indoc;
 use serde::{Deserialize, Serialize};
 use sqlx::postgres::{PgPool, PgQueryAs};
@@ -19,6 +20,16 @@ pub struct Teacher {
     associated_account_id: Option<AccountID>,
 }
 
+/// Pending request for an account to claim a teacher record
+#[derive(Serialize, Clone, Debug, sqlx::FromRow)]
+pub struct AffiliationInvite {
+    teacher_id: TeacherID,
+    first_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_name: Option<String>,
+    created_at: NaiveDateTime,
+}
+
 impl Teacher {
     pub async fn by_id(db: &PgPool, teacher_id: TeacherID) -> sqlx::Result<Option<Teacher>> {
         sqlx::query_as("SELECT id, first_name, last_name, account_id FROM Teacher WHERE id = $1")
@@ -47,7 +58,7 @@ impl Teacher {
 
         TeacherPermission::save_in_transaction(
             &mut transaction,
-            PermissionType::ReadWrite,
+            PermissionType::Owner,
             &id,
             owner,
         )
@@ -124,13 +135,102 @@ impl Teacher {
 
     pub async fn of_user(db: &PgPool, account_id: &AccountID) -> sqlx::Result<Vec<Teacher>> {
         sqlx::query_as(indoc! {"
-            SELECT id, first_name, last_name, Teacher.account_id
+            SELECT DISTINCT id, first_name, last_name, Teacher.account_id
             FROM Teacher
-            JOIN TeacherPermission ON Teacher.id = TeacherPermission.teacher_id
-            WHERE TeacherPermission.account_id = $1
+            JOIN EffectiveTeacherPermission ON Teacher.id = EffectiveTeacherPermission.teacher_id
+            WHERE EffectiveTeacherPermission.account_id = $1
         "})
         .bind(account_id)
         .fetch_all(db)
         .await
     }
+
+    pub async fn invite_affiliate(
+        db: &PgPool,
+        teacher_id: &TeacherID,
+        account_id: &AccountID,
+    ) -> sqlx::Result<()> {
+        sqlx::query(indoc! {"
+            INSERT INTO TeacherAffiliationInvite (teacher_id, account_id) VALUES ($1, $2)
+            ON CONFLICT ON CONSTRAINT teacheraffiliationinvite_unique_connection DO NOTHING
+        "})
+        .bind(teacher_id)
+        .bind(account_id)
+        .execute(db)
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn affiliation_invites(
+        db: &PgPool,
+        account_id: &AccountID,
+    ) -> sqlx::Result<Vec<AffiliationInvite>> {
+        sqlx::query_as(indoc! {"
+            SELECT teacher_id, first_name, last_name, created_at
+            FROM TeacherAffiliationInvite
+            JOIN Teacher ON Teacher.id = TeacherAffiliationInvite.teacher_id
+            WHERE TeacherAffiliationInvite.account_id = $1
+            ORDER BY created_at
+        "})
+        .bind(account_id)
+        .fetch_all(db)
+        .await
+    }
+
+    /// Links the account to the teacher. While linked, the account may write to the teacher
+    /// and read every lesson the teacher teaches, see `EffectiveTeacherPermission`.
+    /// Returns `false` if there was no pending invite
+    pub async fn accept_affiliation(
+        db: &PgPool,
+        teacher_id: &TeacherID,
+        account_id: &AccountID,
+    ) -> sqlx::Result<bool> {
+        let mut transaction = db.begin().await?;
+
+        let deleted = sqlx::query(
+            "DELETE FROM TeacherAffiliationInvite WHERE teacher_id = $1 AND account_id = $2",
+        )
+        .bind(teacher_id)
+        .bind(account_id)
+        .execute(&mut transaction)
+        .await?;
+
+        if deleted == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query("DELETE FROM TeacherAffiliationInvite WHERE teacher_id = $1")
+            .bind(teacher_id)
+            .execute(&mut transaction)
+            .await?;
+
+        sqlx::query("UPDATE Teacher SET account_id = $2 WHERE id = $1")
+            .bind(teacher_id)
+            .bind(account_id)
+            .execute(&mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+
+    /// Unlinks the account, taking the access it had through the affiliation away with it
+    pub async fn remove_affiliation(db: &PgPool, teacher_id: &TeacherID) -> sqlx::Result<()> {
+        let mut transaction = db.begin().await?;
+
+        sqlx::query("DELETE FROM TeacherAffiliationInvite WHERE teacher_id = $1")
+            .bind(teacher_id)
+            .execute(&mut transaction)
+            .await?;
+
+        sqlx::query("UPDATE Teacher SET account_id = NULL WHERE id = $1")
+            .bind(teacher_id)
+            .execute(&mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
 }
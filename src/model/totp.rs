@@ -0,0 +1,192 @@
+use chrono::NaiveDateTime;
+use indoc::indoc;
+use sqlx::postgres::{PgPool, PgQueryAs};
+
+use super::account::AccountID;
+use super::templated_insert;
+use crate::types::Transaction;
+
+/// Authenticator of an account. Logging in requires a code from it once the enrollment is confirmed
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Totp {
+    /// Secret shared with the authenticator, sealed with the key encryption key
+    pub sealed_secret: Vec<u8>,
+    pub confirmed_at: Option<NaiveDateTime>,
+    /// Step of the last accepted code, codes of it and of earlier steps are not accepted again
+    pub last_used_step: Option<i64>,
+}
+
+impl Totp {
+    pub async fn by_account(db: &PgPool, account_id: &AccountID) -> sqlx::Result<Option<Totp>> {
+        sqlx::query_as(
+            "SELECT sealed_secret, confirmed_at, last_used_step FROM Totp WHERE account_id = $1",
+        )
+        .bind(account_id)
+        .fetch_optional(db)
+        .await
+    }
+
+    /// Accounts and secrets stored before secrets were sealed
+    pub async fn unsealed(db: &PgPool) -> sqlx::Result<Vec<(AccountID, Vec<u8>)>> {
+        sqlx::query_as("SELECT account_id, secret FROM Totp WHERE sealed_secret IS NULL")
+            .fetch_all(db)
+            .await
+    }
+
+    /// Replaces the plaintext secret with the sealed one
+    pub async fn seal(
+        db: &PgPool,
+        account_id: &AccountID,
+        sealed_secret: &[u8],
+    ) -> sqlx::Result<()> {
+        sqlx::query("UPDATE Totp SET sealed_secret = $2, secret = NULL WHERE account_id = $1")
+            .bind(account_id)
+            .bind(sealed_secret)
+            .execute(db)
+            .await
+            .map(|_| ())
+    }
+
+    /// Replaces an unconfirmed enrollment.
+    /// Returns `false` when the account already has a confirmed one
+    pub async fn enroll(
+        db: &PgPool,
+        account_id: &AccountID,
+        sealed_secret: &[u8],
+    ) -> sqlx::Result<bool> {
+        sqlx::query(indoc! {"
+            INSERT INTO Totp (account_id, sealed_secret) VALUES ($1, $2)
+            ON CONFLICT (account_id) DO UPDATE SET sealed_secret = $2, last_used_step = NULL
+            WHERE Totp.confirmed_at IS NULL
+        "})
+        .bind(account_id)
+        .bind(sealed_secret)
+        .execute(db)
+        .await
+        .map(|affected| affected > 0)
+    }
+
+    /// Marks the code of the step as used.
+    /// Returns `false` when the step or a later one has already been used
+    pub async fn use_step(db: &PgPool, account_id: &AccountID, step: i64) -> sqlx::Result<bool> {
+        sqlx::query(indoc! {"
+            UPDATE Totp SET last_used_step = $2
+            WHERE account_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+        "})
+        .bind(account_id)
+        .bind(step)
+        .execute(db)
+        .await
+        .map(|affected| affected > 0)
+    }
+
+    /// Completes the enrollment with the first code from the authenticator
+    pub async fn confirm(
+        db: &PgPool,
+        account_id: &AccountID,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> sqlx::Result<bool> {
+        let mut transaction = db.begin().await?;
+
+        let confirmed = sqlx::query(indoc! {"
+            UPDATE Totp SET confirmed_at = (now() AT TIME ZONE 'utc'), last_used_step = $2
+            WHERE account_id = $1 AND confirmed_at IS NULL
+                AND (last_used_step IS NULL OR last_used_step < $2)
+        "})
+        .bind(account_id)
+        .bind(step)
+        .execute(&mut transaction)
+        .await?;
+        if confirmed == 0 {
+            return Ok(false);
+        }
+
+        Self::replace_recovery_codes_in_transaction(
+            &mut transaction,
+            account_id,
+            recovery_code_hashes,
+        )
+        .await?;
+
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn replace_recovery_codes(
+        db: &PgPool,
+        account_id: &AccountID,
+        recovery_code_hashes: &[String],
+    ) -> sqlx::Result<()> {
+        let mut transaction = db.begin().await?;
+        Self::replace_recovery_codes_in_transaction(
+            &mut transaction,
+            account_id,
+            recovery_code_hashes,
+        )
+        .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn replace_recovery_codes_in_transaction(
+        transaction: &mut Transaction,
+        account_id: &AccountID,
+        recovery_code_hashes: &[String],
+    ) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM RecoveryCode WHERE account_id = $1")
+            .bind(account_id)
+            .execute(&mut *transaction)
+            .await?;
+
+        if recovery_code_hashes.is_empty() {
+            return Ok(());
+        }
+
+        let values = (0..recovery_code_hashes.len())
+            .map(|i| templated_insert(2, i))
+            .collect::<Vec<String>>()
+            .join(",");
+        let sql = format!(
+            "INSERT INTO RecoveryCode (account_id, code_hash) VALUES {}",
+            values
+        );
+
+        let mut query = sqlx::query(&sql[..]);
+        for code_hash in recovery_code_hashes {
+            query = query.bind(account_id).bind(code_hash);
+        }
+        query.execute(&mut *transaction).await.map(|_| ())
+    }
+
+    /// Spends the recovery code. Returns `false` if the account has no such unused code
+    pub async fn use_recovery_code(
+        db: &PgPool,
+        account_id: &AccountID,
+        code_hash: &str,
+    ) -> sqlx::Result<bool> {
+        sqlx::query("DELETE FROM RecoveryCode WHERE account_id = $1 AND code_hash = $2")
+            .bind(account_id)
+            .bind(code_hash)
+            .execute(db)
+            .await
+            .map(|affected| affected > 0)
+    }
+
+    pub async fn disable(db: &PgPool, account_id: &AccountID) -> sqlx::Result<()> {
+        let mut transaction = db.begin().await?;
+
+        sqlx::query("DELETE FROM RecoveryCode WHERE account_id = $1")
+            .bind(account_id)
+            .execute(&mut transaction)
+            .await?;
+
+        sqlx::query("DELETE FROM Totp WHERE account_id = $1")
+            .bind(account_id)
+            .execute(&mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+        Ok(())
+    }
+}
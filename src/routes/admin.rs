@@ -0,0 +1,75 @@
+use actix_web::{get, post, web, HttpRequest};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use crate::error::{APIError, Result};
+use crate::keyring::{retired_after, Keyring};
+use crate::model::signing_key::StoredKey;
+
+/// Digest of `ADMIN_SECRET`, admin routes are disabled without the variable
+#[derive(Clone)]
+pub struct AdminSecret(Option<[u8; 32]>);
+
+impl AdminSecret {
+    pub fn from_env() -> Self {
+        let digest = std::env::var("ADMIN_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .map(|secret| Sha256::digest(secret.as_bytes()).into());
+        Self(digest)
+    }
+}
+
+/// Admin routes are open to requests carrying `ADMIN_SECRET` in the `X-Admin-Secret` header
+fn ensure_admin(req: &HttpRequest, secret: &AdminSecret) -> std::result::Result<(), APIError> {
+    let secret = secret.0.as_ref().ok_or(APIError::NotAnAdmin)?;
+    let given = req
+        .headers()
+        .get("X-Admin-Secret")
+        .map(|value| Sha256::digest(value.as_bytes()))
+        .ok_or(APIError::NotAnAdmin)?;
+
+    // Constant time comparison of equally long digests, the secret must not leak through response timing
+    let difference = given
+        .iter()
+        .zip(secret.iter())
+        .fold(0_u8, |difference, (a, b)| difference | (a ^ b));
+    if difference != 0 {
+        return Err(APIError::NotAnAdmin);
+    }
+    Ok(())
+}
+
+/// Keys that still verify tokens, the one activated last first
+#[get("/admin/signing-keys")]
+pub async fn get_signing_keys(
+    db: web::Data<PgPool>,
+    admin_secret: web::Data<AdminSecret>,
+    req: HttpRequest,
+) -> Result<Vec<StoredKey>> {
+    ensure_admin(&req, &admin_secret)?;
+    Ok(StoredKey::valid(db.get_ref(), &retired_after())
+        .await?
+        .into())
+}
+
+/// Publishes a new key which replaces the current one once every instance has loaded it
+#[post("/admin/signing-keys")]
+pub async fn rotate_signing_key(
+    db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    admin_secret: web::Data<AdminSecret>,
+    req: HttpRequest,
+) -> Result<Vec<StoredKey>> {
+    ensure_admin(&req, &admin_secret)?;
+    let kid = keyring.rotate(db.get_ref()).await?;
+    log::info!("Signing key rotated, tokens are going to be signed with {}", kid);
+
+    Ok(StoredKey::valid(db.get_ref(), &retired_after())
+        .await?
+        .into())
+}
+
+pub fn configure_admin_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_signing_keys).service(rotate_signing_key);
+}
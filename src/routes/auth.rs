@@ -1,15 +1,25 @@
-use actix_web::{post, web, HttpResponse};
+use actix_web::{delete, get, http::header, post, put, web, HttpRequest, HttpResponse};
 use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 
-use crate::error::{APIError, Result};
-use crate::middleware::Authentication;
-use crate::model::account::{Account, AccountID};
+use crate::error::{APIError, RequestScope, Result};
+use crate::keyring::{Jwks, Keyring};
+use crate::middleware::{Authentication, PathExtractor};
+use crate::mailer::{Mail, SharedMailer};
+use crate::model::{
+    account::{Account, AccountID},
+    session::{Session, SessionID},
+    totp::Totp,
+};
 use crate::token::{
-    generate_token_pair, AccessToken, AccessTokenInfo, ApplicationClaim, ApplicationToken,
-    RefreshToken
+    deleted_user_key, email_confirmation_key, ensure_not_revoked, generate_token_pair,
+    password_reset_key, revoke_refresh_token, revoke_tokens_issued_until_now, totp_challenge_key,
+    totp_failures_key, AccessToken, AccessTokenInfo, ApplicationClaim, ApplicationToken,
+    EmailConfirmationInfo, EmailConfirmationToken, PasswordResetInfo, PasswordResetToken,
+    RefreshToken, RefreshTokenID, RefreshTokenInfo, TotpChallengeInfo, TotpChallengeToken,
 };
+use crate::totp;
 use crate::types::RedisPool;
 
 #[derive(Deserialize)]
@@ -24,11 +34,86 @@ pub struct AuthorizationResponse {
     refresh_token: RefreshToken,
 }
 
+/// User agent and address of the client, recorded to tell sessions apart.
+/// The address is the one of the connection, forwarding headers are set by the client and can't be trusted
+fn client_details(req: &HttpRequest) -> (Option<String>, Option<String>) {
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let ip = req.peer_addr().map(|address| address.ip().to_string());
+    (user_agent, ip)
+}
+
+async fn start_session(
+    db: &PgPool,
+    keyring: &Keyring,
+    req: &HttpRequest,
+    account_id: AccountID,
+) -> std::result::Result<AuthorizationResponse, APIError> {
+    let token_id = RefreshTokenID::generate();
+    let (user_agent, ip) = client_details(req);
+    let session_id = Session::create(db, &account_id, &token_id, user_agent, ip).await?;
+
+    let (access_token, refresh_token) = generate_token_pair(keyring, account_id, session_id, token_id)?;
+    Ok(AuthorizationResponse {
+        access_token,
+        refresh_token,
+    })
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Authorized(AuthorizationResponse),
+    /// The account has two-factor authentication enabled, the login is finished at `/auth/login/totp`
+    TotpRequired { totp_challenge: TotpChallengeToken },
+}
+
+/// Wrong codes a login challenge survives
+const TOTP_CHALLENGE_ATTEMPTS: i64 = 5;
+/// Wrong codes after which no code of the account is accepted, however many requests they were spread over
+const TOTP_FAILURE_LIMIT: i64 = 10;
+/// Codes stay locked this long after the last wrong one
+const TOTP_LOCKOUT_SECONDS: usize = 15 * 60;
+
+pub async fn ensure_totp_not_locked(
+    redis: &mut deadpool_redis::Connection,
+    account_id: &AccountID,
+) -> std::result::Result<(), APIError> {
+    let failures: Option<i64> = redis.get(totp_failures_key(account_id)).await?;
+    if failures.unwrap_or_default() >= TOTP_FAILURE_LIMIT {
+        return Err(APIError::TooManyAttempts);
+    }
+    Ok(())
+}
+
+/// Every failure extends the lockout, so at most `TOTP_FAILURE_LIMIT` codes are guessed per lockout window
+pub async fn record_totp_failure(
+    redis: &mut deadpool_redis::Connection,
+    account_id: &AccountID,
+) -> std::result::Result<(), APIError> {
+    let key = totp_failures_key(account_id);
+    let failures: i64 = redis.incr(&key, 1).await?;
+    redis.expire::<_, ()>(&key, TOTP_LOCKOUT_SECONDS).await?;
+    if failures == TOTP_FAILURE_LIMIT {
+        log::warn!(
+            "Too many wrong one-time passwords, locking them for account {}",
+            account_id
+        );
+    }
+    Ok(())
+}
+
 #[post("/auth/login")]
 pub async fn login(
     db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    redis_pool: web::Data<RedisPool>,
+    req: HttpRequest,
     login_data: web::Json<LoginData>,
-) -> Result<AuthorizationResponse> {
+) -> Result<LoginResponse> {
     let LoginData { login, password } = login_data.into_inner();
     let account = Account::get_by_login(db.get_ref(), login).await?;
 
@@ -43,13 +128,106 @@ pub async fn login(
         return Err(APIError::InvalidCredentials);
     }
 
-    let (access_token, refresh_token) = generate_token_pair(account.id)?;
+    let totp = Totp::by_account(db.get_ref(), &account.id).await?;
+    if totp.map_or(false, |totp| totp.confirmed_at.is_some()) {
+        let mut redis = redis_pool.get().await?;
+        ensure_totp_not_locked(&mut redis, &account.id).await?;
 
-    Ok(AuthorizationResponse {
-        access_token,
-        refresh_token,
+        let challenge_id = uuid::Uuid::new_v4();
+        let totp_challenge = TotpChallengeToken::generate_token(
+            &keyring,
+            TotpChallengeInfo {
+                account_id: account.id,
+                challenge_id,
+            },
+        )?;
+
+        let ttl = TotpChallengeToken::valid_for().num_seconds();
+        redis
+            .set_ex::<_, _, ()>(
+                totp_challenge_key(&challenge_id),
+                TOTP_CHALLENGE_ATTEMPTS,
+                ttl as usize,
+            )
+            .await?;
+
+        return Ok(LoginResponse::TotpRequired { totp_challenge }.into());
     }
-    .into())
+
+    let authorization = start_session(db.get_ref(), &keyring, &req, account.id).await?;
+    Ok(LoginResponse::Authorized(authorization).into())
+}
+
+#[derive(Deserialize)]
+pub struct TotpLoginData {
+    totp_challenge: TotpChallengeToken,
+    code: Option<String>,
+    /// Used instead of the code when the authenticator is not at hand
+    recovery_code: Option<String>,
+}
+
+#[post("/auth/login/totp")]
+pub async fn login_totp(
+    db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    redis_pool: web::Data<RedisPool>,
+    req: HttpRequest,
+    login_data: web::Json<TotpLoginData>,
+) -> Result<AuthorizationResponse> {
+    let TotpLoginData {
+        totp_challenge,
+        code,
+        recovery_code,
+    } = login_data.into_inner();
+    let TotpChallengeInfo {
+        account_id,
+        challenge_id,
+    } = totp_challenge.authenticate_claim(&keyring)?.inner;
+
+    // Every attempt counts, so the code cannot be guessed within the lifetime of the challenge
+    let mut redis = redis_pool.get().await?;
+    ensure_totp_not_locked(&mut redis, &account_id).await?;
+    let key = totp_challenge_key(&challenge_id);
+    let attempts_left: i64 = redis.incr(&key, -1).await?;
+    if attempts_left < 0 {
+        redis.del::<_, ()>(&key).await?;
+        return Err(APIError::TokenRevoked);
+    }
+
+    let totp = Totp::by_account(db.get_ref(), &account_id)
+        .await?
+        .filter(|totp| totp.confirmed_at.is_some())
+        .ok_or(APIError::TokenRevoked)?;
+
+    let accepted = match (code, recovery_code) {
+        (Some(code), _) => match totp::matching_step(
+            &keyring.unseal_totp_secret(&account_id, &totp.sealed_secret)?,
+            &code,
+            totp::current_step(),
+            totp.last_used_step,
+        )? {
+            Some(step) => Totp::use_step(db.get_ref(), &account_id, step).await?,
+            None => false,
+        },
+        (None, Some(recovery_code)) => {
+            let code_hash = totp::hash_recovery_code(&recovery_code);
+            Totp::use_recovery_code(db.get_ref(), &account_id, &code_hash).await?
+        }
+        (None, None) => {
+            return Err(APIError::BadRequest {
+                message: "Either `code` or `recovery_code` is required".to_string(),
+                scope: Some(RequestScope::Body),
+            })
+        }
+    };
+    if !accepted {
+        record_totp_failure(&mut redis, &account_id).await?;
+        return Err(APIError::InvalidTotpCode);
+    }
+
+    redis.del::<_, ()>(&key).await?;
+    redis.del::<_, ()>(totp_failures_key(&account_id)).await?;
+    Ok(start_session(db.get_ref(), &keyring, &req, account_id).await?.into())
 }
 
 #[derive(Deserialize)]
@@ -58,11 +236,17 @@ pub struct RegistrationData {
     password: String,
     first_name: String,
     last_name: Option<String>,
+    email: Option<String>,
 }
 
+/// The optional email is only set on the account once confirmed with the mailed token
 #[post("/auth/register")]
 pub async fn register(
     db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    redis_pool: web::Data<RedisPool>,
+    mailer: web::Data<SharedMailer>,
+    req: HttpRequest,
     registration_data: web::Json<RegistrationData>,
 ) -> Result<AuthorizationResponse> {
     let RegistrationData {
@@ -70,6 +254,7 @@ pub async fn register(
         password,
         first_name,
         last_name,
+        email,
     } = registration_data.into_inner();
 
     let account = Account::register(
@@ -81,28 +266,45 @@ pub async fn register(
     )
     .await?;
 
-    let (access_token, refresh_token) = generate_token_pair(account.id)?;
-
-    Ok(AuthorizationResponse {
-        access_token,
-        refresh_token,
+    if let Some(email) = email {
+        let keyring = keyring.clone();
+        let (account_id, first_name) = (account.id, account.first_name.clone());
+        actix_rt::spawn(async move {
+            let sent = send_email_confirmation(
+                &keyring, &redis_pool, &mailer, account_id, first_name, email,
+            )
+            .await;
+            if let Err(error) = sent {
+                log::error!("Cannot send an email confirmation mail: {}", error);
+            }
+        });
     }
-    .into())
+
+    Ok(start_session(db.get_ref(), &keyring, &req, account.id).await?.into())
 }
 
 #[post("/auth/revoke", wrap = "Authentication")]
 pub async fn revoke(
+    db: web::Data<PgPool>,
     redis_pool: web::Data<RedisPool>,
     account_id: AccountID,
     claim: ApplicationClaim<AccessTokenInfo>,
 ) -> std::result::Result<HttpResponse, APIError> {
     let mut redis = redis_pool.get().await?;
 
-    let key = format!("revoked_token:{}:{}", account_id, claim.inner.token_origin);
-    let now = chrono::Utc::now().timestamp();
-    let ttl = claim.registered.expiration - now;
-    if ttl > 0 {
-        redis.set_ex(key, 1_u8, ttl as usize).await?;
+    // Deleting the session stops the refresh token, only access tokens need the key
+    revoke_refresh_token(
+        &mut redis,
+        &account_id,
+        &claim.inner.token_origin,
+        claim.registered.expiration,
+    )
+    .await?;
+    // The access token may predate the last refresh of the session, tokens of the new one go too
+    let session = Session::delete_by_token(db.get_ref(), &claim.inner.token_origin).await?;
+    if let Some(session) = session.filter(|session| session.token_id != claim.inner.token_origin) {
+        revoke_refresh_token(&mut redis, &account_id, &session.token_id, session.expires_at())
+            .await?;
     }
 
     Ok(HttpResponse::NoContent().finish())
@@ -115,36 +317,69 @@ pub struct RefreshTokenRequest {
 
 #[post("/auth/refresh")]
 pub async fn refresh(
+    db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
     redis_pool: web::Data<RedisPool>,
+    req: HttpRequest,
     request: web::Json<RefreshTokenRequest>,
 ) -> Result<AuthorizationResponse> {
     let mut redis = redis_pool.get().await?;
 
     let RefreshTokenRequest { refresh_token } = request.into_inner();
-    let claim = refresh_token.authenticate_claim()?;
-    let token_key = format!(
-        "revoked_token:{}:{}",
-        claim.inner.account_id, claim.inner.token_id
-    );
-    let revoked: Option<u8> = redis.get(token_key).await?;
-    if let Some(1) = revoked {
-        return Err(APIError::TokenRevoked);
-    }
+    let claim = refresh_token.authenticate_claim(&keyring)?;
+    let RefreshTokenInfo {
+        account_id,
+        token_id: old_token_id,
+        session_id,
+    } = claim.inner;
+    ensure_not_revoked(
+        &mut redis,
+        &account_id,
+        &old_token_id,
+        claim.registered.issued_at,
+    )
+    .await?;
 
-    let user_key = format!("deleted_user:{}", claim.inner.account_id);
-    let revoked_user: Option<u8> = redis.get(user_key).await?;
-    if let Some(1) = revoked_user {
+    let token_id = RefreshTokenID::generate();
+    let (user_agent, ip) = client_details(&req);
+    let rotated = Session::rotate(
+        db.get_ref(),
+        &session_id,
+        &old_token_id,
+        &token_id,
+        user_agent,
+        ip,
+    )
+    .await?;
+
+    if !rotated {
+        // Concurrent refreshes with the same token, only the first one gets the new pair
+        if Session::rotated_recently_from(db.get_ref(), &session_id, &old_token_id).await? {
+            return Err(APIError::TokenAlreadyRefreshed);
+        }
+
+        // The token has already been refreshed, so either copy may be in the wrong hands.
+        // The whole family goes, the owner has to log in again
+        if let Some(session) = Session::delete(db.get_ref(), &session_id, &account_id).await? {
+            log::warn!(
+                "Refresh token {} of session {} was reused, revoking the session of account {}",
+                old_token_id,
+                session_id,
+                account_id
+            );
+            revoke_refresh_token(
+                &mut redis,
+                &account_id,
+                &session.token_id,
+                session.expires_at(),
+            )
+            .await?;
+        }
         return Err(APIError::TokenRevoked);
     }
 
-    let revoke_token_key = format!(
-        "revoked_token:{}:{}",
-        claim.inner.account_id,
-        refresh_token.str_ref()
-    );
-    redis.set(revoke_token_key, 1_u8).await?;
-
-    let (access_token, refresh_token) = generate_token_pair(claim.inner.account_id)?;
+    let (access_token, refresh_token) =
+        generate_token_pair(&keyring, account_id, session_id, token_id)?;
 
     Ok(AuthorizationResponse {
         access_token,
@@ -153,11 +388,375 @@ pub async fn refresh(
     .into())
 }
 
-// TODO: delete user
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    old_password: String,
+    new_password: String,
+}
+
+/// Tokens issued before the change stop working, the caller gets a fresh pair
+#[post("/auth/password", wrap = "Authentication")]
+pub async fn change_password(
+    db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    redis_pool: web::Data<RedisPool>,
+    req: HttpRequest,
+    account_id: AccountID,
+    request: web::Json<ChangePasswordRequest>,
+) -> Result<AuthorizationResponse> {
+    let ChangePasswordRequest {
+        old_password,
+        new_password,
+    } = request.into_inner();
+    let account = Account::by_id(db.get_ref(), &account_id)
+        .await?
+        .ok_or(APIError::AccountDoesNotExist)?;
+
+    if !bcrypt::verify(old_password, &account.password_hash[..])? {
+        return Err(APIError::InvalidCredentials);
+    }
+
+    Account::change_password(db.get_ref(), &account_id, new_password).await?;
+
+    let mut redis = redis_pool.get().await?;
+    revoke_tokens_issued_until_now(&mut redis, &account_id).await?;
+    Session::delete_all_of_account(db.get_ref(), &account_id).await?;
+
+    Ok(start_session(db.get_ref(), &keyring, &req, account_id).await?.into())
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    email: String,
+}
+
+async fn send_password_reset(
+    db: &PgPool,
+    keyring: &Keyring,
+    redis_pool: &RedisPool,
+    mailer: &SharedMailer,
+    email: String,
+) -> std::result::Result<(), APIError> {
+    let account = match Account::by_email(db, &email).await? {
+        Some(account) => account,
+        None => return Ok(()),
+    };
+
+    let reset_id = uuid::Uuid::new_v4();
+    let token = PasswordResetToken::generate_token(keyring, PasswordResetInfo {
+        account_id: account.id,
+        reset_id,
+    })?;
+
+    let mut redis = redis_pool.get().await?;
+    let ttl = PasswordResetToken::valid_for().num_seconds();
+    redis
+        .set_ex::<_, _, ()>(password_reset_key(&reset_id), 1_u8, ttl as usize)
+        .await?;
+
+    let link = std::env::var("PASSWORD_RESET_URL")
+        .ok()
+        .filter(|url| !url.is_empty())
+        .map(|url| format!("\n\n{}?token={}", url, token))
+        .unwrap_or_default();
+    mailer
+        .send(Mail {
+            to: email,
+            subject: "Password reset".to_string(),
+            body: format!(
+                "Hi {}!\n\nUse the following token to reset the password of `{}`. \
+                It is valid for {} minutes.\n\n{}{}",
+                account.first_name,
+                account.login,
+                PasswordResetToken::valid_for().num_minutes(),
+                token,
+                link
+            ),
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Mails a reset token to the account. The request is answered before the account is even looked up,
+/// so neither the response nor its timing tells whether the account exists
+#[post("/auth/password-reset")]
+pub async fn request_password_reset(
+    db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    redis_pool: web::Data<RedisPool>,
+    mailer: web::Data<SharedMailer>,
+    request: web::Json<PasswordResetRequest>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let PasswordResetRequest { email } = request.into_inner();
+
+    actix_rt::spawn(async move {
+        let sent = send_password_reset(&db, &keyring, &redis_pool, &mailer, email).await;
+        if let Err(error) = sent {
+            log::error!("Cannot send a password reset mail: {}", error);
+        }
+    });
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetConfirmation {
+    token: PasswordResetToken,
+    new_password: String,
+}
+
+#[post("/auth/password-reset/confirm")]
+pub async fn confirm_password_reset(
+    db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    redis_pool: web::Data<RedisPool>,
+    request: web::Json<PasswordResetConfirmation>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let PasswordResetConfirmation {
+        token,
+        new_password,
+    } = request.into_inner();
+    let PasswordResetInfo {
+        account_id,
+        reset_id,
+    } = token.authenticate_claim(&keyring)?.inner;
+
+    let mut redis = redis_pool.get().await?;
+    let removed: u8 = redis.del(password_reset_key(&reset_id)).await?;
+    if removed == 0 {
+        return Err(APIError::TokenRevoked);
+    }
+
+    Account::change_password(db.get_ref(), &account_id, new_password).await?;
+    revoke_tokens_issued_until_now(&mut redis, &account_id).await?;
+    Session::delete_all_of_account(db.get_ref(), &account_id).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct SetEmailRequest {
+    email: Option<String>,
+}
+
+async fn send_email_confirmation(
+    keyring: &Keyring,
+    redis_pool: &RedisPool,
+    mailer: &SharedMailer,
+    account_id: AccountID,
+    first_name: String,
+    email: String,
+) -> std::result::Result<(), APIError> {
+    let confirmation_id = uuid::Uuid::new_v4();
+    let token = EmailConfirmationToken::generate_token(keyring, EmailConfirmationInfo {
+        account_id,
+        email: email.clone(),
+        confirmation_id,
+    })?;
+
+    let mut redis = redis_pool.get().await?;
+    let ttl = EmailConfirmationToken::valid_for().num_seconds();
+    redis
+        .set_ex::<_, _, ()>(email_confirmation_key(&confirmation_id), 1_u8, ttl as usize)
+        .await?;
+
+    let link = std::env::var("EMAIL_CONFIRMATION_URL")
+        .ok()
+        .filter(|url| !url.is_empty())
+        .map(|url| format!("\n\n{}?token={}", url, token))
+        .unwrap_or_default();
+    mailer
+        .send(Mail {
+            to: email,
+            subject: "Email confirmation".to_string(),
+            body: format!(
+                "Hi {}!\n\nUse the following token to confirm this email address. \
+                It is valid for {} hours.\n\n{}{}",
+                first_name,
+                EmailConfirmationToken::valid_for().num_hours(),
+                token,
+                link
+            ),
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// A new email is mailed a confirmation token and only set once confirmed, clearing it is immediate
+#[put("/account/email", wrap = "Authentication")]
+pub async fn set_email(
+    db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    redis_pool: web::Data<RedisPool>,
+    mailer: web::Data<SharedMailer>,
+    account_id: AccountID,
+    request: web::Json<SetEmailRequest>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let SetEmailRequest { email } = request.into_inner();
+    let email = match email {
+        Some(email) => email,
+        None => {
+            Account::set_email(db.get_ref(), &account_id, None).await?;
+            return Ok(HttpResponse::NoContent().finish());
+        }
+    };
+
+    let account = Account::by_id(db.get_ref(), &account_id)
+        .await?
+        .ok_or(APIError::AccountDoesNotExist)?;
+    send_email_confirmation(
+        &keyring,
+        &redis_pool,
+        &mailer,
+        account_id,
+        account.first_name,
+        email,
+    )
+    .await?;
+
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[derive(Deserialize)]
+pub struct EmailConfirmation {
+    token: EmailConfirmationToken,
+}
+
+#[post("/account/email/confirm")]
+pub async fn confirm_email(
+    db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    redis_pool: web::Data<RedisPool>,
+    request: web::Json<EmailConfirmation>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let EmailConfirmation { token } = request.into_inner();
+    let EmailConfirmationInfo {
+        account_id,
+        email,
+        confirmation_id,
+    } = token.authenticate_claim(&keyring)?.inner;
+
+    let mut redis = redis_pool.get().await?;
+    let removed: u8 = redis.del(email_confirmation_key(&confirmation_id)).await?;
+    if removed == 0 {
+        return Err(APIError::TokenRevoked);
+    }
+
+    if !Account::set_email(db.get_ref(), &account_id, Some(email)).await? {
+        return Err(APIError::EmailAlreadyPresent);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountRequest {
+    password: String,
+}
+
+#[delete("/account", wrap = "Authentication")]
+pub async fn delete_account(
+    db: web::Data<PgPool>,
+    redis_pool: web::Data<RedisPool>,
+    account_id: AccountID,
+    request: web::Json<DeleteAccountRequest>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let DeleteAccountRequest { password } = request.into_inner();
+    let account = Account::by_id(db.get_ref(), &account_id)
+        .await?
+        .ok_or(APIError::AccountDoesNotExist)?;
+
+    if !bcrypt::verify(password, &account.password_hash[..])? {
+        return Err(APIError::InvalidCredentials);
+    }
+
+    // Outstanding tokens die before the account does, so none survives a Redis failure.
+    // The tombstone outlives the longest living token
+    let mut redis = redis_pool.get().await?;
+    let key = deleted_user_key(&account_id);
+    let ttl = RefreshToken::valid_for().num_seconds();
+    redis.set_ex::<_, _, ()>(&key, 1_u8, ttl as usize).await?;
+
+    if let Err(error) = Account::delete(db.get_ref(), &account_id).await {
+        redis.del::<_, ()>(&key).await?;
+        return Err(error.into());
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Serialize)]
+pub struct SessionResponse {
+    #[serde(flatten)]
+    session: Session,
+    /// Whether the session is the one the request was made from
+    current: bool,
+}
+
+#[get("/auth/sessions", wrap = "Authentication")]
+pub async fn get_sessions(
+    db: web::Data<PgPool>,
+    account_id: AccountID,
+    claim: ApplicationClaim<AccessTokenInfo>,
+) -> Result<Vec<SessionResponse>> {
+    let sessions = Session::of_account(db.get_ref(), &account_id)
+        .await?
+        .into_iter()
+        .map(|session| SessionResponse {
+            current: session.token_id == claim.inner.token_origin,
+            session,
+        })
+        .collect::<Vec<_>>();
+    Ok(sessions.into())
+}
+
+#[delete(
+    "/auth/sessions/{id}",
+    wrap = "PathExtractor::<SessionID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn delete_session(
+    db: web::Data<PgPool>,
+    redis_pool: web::Data<RedisPool>,
+    session_id: SessionID,
+    account_id: AccountID,
+) -> std::result::Result<HttpResponse, APIError> {
+    let session = Session::delete(db.get_ref(), &session_id, &account_id)
+        .await?
+        .ok_or(APIError::SessionDoesNotExist)?;
+
+    let mut redis = redis_pool.get().await?;
+    revoke_refresh_token(
+        &mut redis,
+        &account_id,
+        &session.token_id,
+        session.expires_at(),
+    )
+    .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Public keys other services verify our tokens with
+#[get("/.well-known/jwks.json")]
+pub async fn jwks(keyring: web::Data<Keyring>) -> web::Json<Jwks> {
+    web::Json(keyring.jwks())
+}
 
 pub fn configure_auth_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(login)
+        .service(login_totp)
         .service(register)
         .service(revoke)
-        .service(refresh);
+        .service(refresh)
+        .service(change_password)
+        .service(request_password_reset)
+        .service(confirm_password_reset)
+        .service(set_email)
+        .service(confirm_email)
+        .service(delete_account)
+        .service(get_sessions)
+        .service(delete_session)
+        .service(jwks);
 }
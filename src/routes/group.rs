@@ -0,0 +1,110 @@
+use actix_web::{delete, get, patch, put, web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::error::{APIError, Result};
+use crate::middleware::{Authentication, CheckPermission, PathExtractor};
+use crate::model::{
+    account::AccountID,
+    group::{Group, GroupID},
+    permission::{EntityPermission, GroupPermission, PermissionType},
+};
+use crate::payload::Payload;
+use crate::routes::permission::configure_permission_routes;
+
+#[get(
+    "/group/{id}",
+    wrap = "CheckPermission::<GroupPermission>::new(PermissionType::Read)",
+    wrap = "PathExtractor::<GroupID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn get_group(db: web::Data<PgPool>, group_id: GroupID) -> Result<Group> {
+    Group::by_id(db.get_ref(), group_id)
+        .await?
+        .ok_or(APIError::GroupDoesNotExist)
+        .map(Payload::from)
+}
+
+#[derive(Deserialize)]
+pub struct GroupRequest {
+    name: String,
+}
+
+#[put("/group", wrap = "Authentication")]
+pub async fn put_group(
+    db: web::Data<PgPool>,
+    group: web::Json<GroupRequest>,
+    account_id: AccountID,
+) -> Result<Group> {
+    let GroupRequest { name } = group.into_inner();
+    Ok(Group::create(db.get_ref(), name, &account_id).await?.into())
+}
+
+#[patch(
+    "/group/{id}",
+    wrap = "CheckPermission::<GroupPermission>::new(PermissionType::ReadWrite)",
+    wrap = "PathExtractor::<GroupID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn patch_group(
+    db: web::Data<PgPool>,
+    group_id: GroupID,
+    patch: web::Json<GroupRequest>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let GroupRequest { name } = patch.into_inner();
+    Group::rename(db.get_ref(), &group_id, name).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete(
+    "/group/{id}",
+    wrap = "CheckPermission::<GroupPermission>::new(PermissionType::Owner)",
+    wrap = "PathExtractor::<GroupID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn delete_group(
+    db: web::Data<PgPool>,
+    group_id: GroupID,
+) -> std::result::Result<HttpResponse, APIError> {
+    Group::delete(db.get_ref(), &group_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/groups", wrap = "Authentication")]
+pub async fn get_groups(db: web::Data<PgPool>, account_id: AccountID) -> Result<Vec<Group>> {
+    Ok(Group::of_user(db.get_ref(), &account_id).await?.into())
+}
+
+#[delete(
+    "/group/{id}/membership",
+    wrap = "CheckPermission::<GroupPermission>::new(PermissionType::Read)",
+    wrap = "PathExtractor::<GroupID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn leave_group(
+    db: web::Data<PgPool>,
+    group_id: GroupID,
+    account_id: AccountID,
+    permission: GroupPermission,
+) -> std::result::Result<HttpResponse, APIError> {
+    if permission.permission() == PermissionType::Owner {
+        return Err(APIError::BadRequest {
+            message: "Owner has to transfer the ownership before leaving the group".to_string(),
+            scope: None,
+        });
+    }
+
+    Group::leave(db.get_ref(), &group_id, &account_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn configure_group_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_group)
+        .service(put_group)
+        .service(patch_group)
+        .service(delete_group)
+        .service(get_groups)
+        .service(leave_group);
+    // Members are managed through permissions and invites of the group
+    configure_permission_routes::<GroupPermission>(cfg, "/group");
+}
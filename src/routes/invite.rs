@@ -0,0 +1,136 @@
+use actix_web::{delete, post, web, HttpResponse};
+use sqlx::PgPool;
+
+use crate::error::{APIError, Result};
+use crate::keyring::Keyring;
+use crate::middleware::{Authentication, PathExtractor};
+use crate::model::{
+    account::AccountID,
+    invite::{Invite, InviteID},
+    permission::{
+        EntityPermission, EntityType, GroupPermission, LessonPermission, PermissionError,
+        PermissionType, TaskPermission, TeacherPermission,
+    },
+};
+use crate::token::{ApplicationToken, InviteInfo, InviteToken};
+use crate::types::Transaction;
+
+/// Grants the invited permission unless the account already has the same or a stronger one.
+/// Returns `false` when nothing was granted
+async fn accept_for<T: EntityPermission>(
+    db: &PgPool,
+    transaction: &mut Transaction,
+    entity_id: T::EntityID,
+    issuer: &AccountID,
+    account_id: &AccountID,
+    permission: PermissionType,
+) -> std::result::Result<bool, APIError> {
+    // Invites stop working once the issuer is no longer in charge of sharing the entity
+    match T::type_of_entity(db, issuer, &entity_id).await {
+        Ok(PermissionType::Owner) => {}
+        Ok(_)
+        | Err(PermissionError::PermissionNotPresent)
+        | Err(PermissionError::EntityNotPresent) => return Err(APIError::InvitationDoesNotExist),
+        Err(error) => return Err(error.into()),
+    }
+
+    match T::type_of_entity(db, account_id, &entity_id).await {
+        Ok(current) if current >= permission => return Ok(false),
+        Ok(_) | Err(PermissionError::PermissionNotPresent) => {}
+        Err(error) => return Err(error.into()),
+    }
+
+    Ok(T::strengthen_in_transaction(transaction, permission, &entity_id, account_id).await?)
+}
+
+/// A use of the invite is only counted when it grants something
+#[post("/invites/{token}/accept", wrap = "Authentication")]
+pub async fn accept_invite(
+    db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    token: web::Path<InviteToken>,
+    account_id: AccountID,
+) -> Result<InviteInfo> {
+    let info = token.authenticate_claim(&keyring)?.inner;
+    let InviteInfo {
+        invite_id,
+        entity_type,
+        entity_id,
+        permission,
+    } = info;
+
+    let db = db.get_ref();
+    let mut transaction = db.begin().await?;
+    let issuer = Invite::redeem_in_transaction(&mut transaction, &invite_id)
+        .await?
+        .ok_or(APIError::InvitationDoesNotExist)?;
+
+    let tx = &mut transaction;
+    let granted = match entity_type {
+        EntityType::Lesson => {
+            accept_for::<LessonPermission>(
+                db,
+                tx,
+                entity_id.into(),
+                &issuer,
+                &account_id,
+                permission,
+            )
+            .await?
+        }
+        EntityType::Teacher => {
+            accept_for::<TeacherPermission>(
+                db,
+                tx,
+                entity_id.into(),
+                &issuer,
+                &account_id,
+                permission,
+            )
+            .await?
+        }
+        EntityType::Task => {
+            accept_for::<TaskPermission>(db, tx, entity_id.into(), &issuer, &account_id, permission)
+                .await?
+        }
+        EntityType::Group => {
+            accept_for::<GroupPermission>(
+                db,
+                tx,
+                entity_id.into(),
+                &issuer,
+                &account_id,
+                permission,
+            )
+            .await?
+        }
+    };
+
+    if granted {
+        transaction.commit().await?;
+    } else {
+        transaction.rollback().await?;
+    }
+
+    Ok(info.into())
+}
+
+#[delete(
+    "/invites/{id}",
+    wrap = "PathExtractor::<InviteID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn revoke_invite(
+    db: web::Data<PgPool>,
+    invite_id: InviteID,
+    account_id: AccountID,
+) -> std::result::Result<HttpResponse, APIError> {
+    if !Invite::revoke(db.get_ref(), &invite_id, &account_id).await? {
+        return Err(APIError::InvitationDoesNotExist);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn configure_invite_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(accept_invite).service(revoke_invite);
+}
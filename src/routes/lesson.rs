@@ -1,18 +1,23 @@
-use actix_web::{delete, get, patch, put, web, HttpResponse};
+use actix_web::{delete, get, patch, post, put, web, HttpResponse};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use std::vec::Vec;
-use chrono::NaiveDate;
+use chrono::{Duration, NaiveDate};
 
-use crate::error::{APIError, Result};
+use crate::error::{APIError, RequestScope, Result};
 use crate::middleware::{Authentication, CheckPermission, PathExtractor};
 use crate::model::{
     account::AccountID,
+    calendar::Occurrence,
     lesson::{Lesson, LessonID},
-    permission::{PermissionType, LessonPermission},
+    permission::{EntityPermission, LessonPermission, PermissionType, TeacherPermission},
     repeat::*,
+    teacher::TeacherID,
 };
 use crate::payload::Payload;
+use crate::routes::permission::{
+    configure_group_permission_routes, configure_permission_routes,
+};
 use crate::util::deserialize_optional_field;
 
 #[get(
@@ -36,6 +41,66 @@ pub struct LessonCreateRequest {
     daily: Option<Vec<DailyRepeat>>,
     weekly: Option<Vec<WeeklyRepeat>>,
     monthly: Option<Vec<MonthlyRepeat>>,
+    exceptions: Option<Vec<RepeatException>>,
+    teachers: Option<Vec<TeacherID>>,
+}
+
+/// Durations are checked by the database too, but a violated constraint would surface as an internal error
+fn check_durations(
+    singles: &Option<Vec<SingleOccurrence>>,
+    daily: &Option<Vec<DailyRepeat>>,
+    weekly: &Option<Vec<WeeklyRepeat>>,
+    monthly: &Option<Vec<MonthlyRepeat>>,
+    exceptions: &Option<Vec<RepeatException>>,
+) -> std::result::Result<(), APIError> {
+    let mut recurrences = singles
+        .iter()
+        .flatten()
+        .map(|r| r as &dyn Recurrence)
+        .chain(daily.iter().flatten().map(|r| r as &dyn Recurrence))
+        .chain(weekly.iter().flatten().map(|r| r as &dyn Recurrence))
+        .chain(monthly.iter().flatten().map(|r| r as &dyn Recurrence));
+
+    let positive = recurrences.all(|recurrence| recurrence.duration() > Duration::zero())
+        && exceptions
+            .iter()
+            .flatten()
+            .filter_map(|exception| exception.duration)
+            .all(|duration| duration > 0);
+
+    if !positive {
+        return Err(APIError::BadRequest {
+            message: "`duration` must be a positive number of minutes".to_string(),
+            scope: Some(RequestScope::Body),
+        });
+    }
+
+    Ok(())
+}
+
+impl LessonCreateRequest {
+    pub fn validated(self) -> std::result::Result<Self, APIError> {
+        check_durations(
+            &self.singles,
+            &self.daily,
+            &self.weekly,
+            &self.monthly,
+            &self.exceptions,
+        )?;
+        Ok(self)
+    }
+}
+
+/// Makes sure the account is able to see every teacher it tries to assign
+async fn check_teachers_access(
+    db: &PgPool,
+    account_id: &AccountID,
+    teachers: &Vec<TeacherID>,
+) -> std::result::Result<(), APIError> {
+    for teacher_id in teachers {
+        TeacherPermission::type_of_entity(db, account_id, teacher_id).await?;
+    }
+    Ok(())
 }
 
 #[put("/lesson", wrap = "Authentication")]
@@ -50,11 +115,16 @@ pub async fn put_lesson(
         singles,
         daily,
         weekly,
-        monthly
-    } = lesson.into_inner();
+        monthly,
+        exceptions,
+        teachers,
+    } = lesson.into_inner().validated()?;
 
     log::info!("Monthlies: {:?}", monthly);
 
+    let teachers = teachers.unwrap_or_default();
+    check_teachers_access(db.get_ref(), &account_id, &teachers).await?;
+
     Ok(Lesson::create(
         db.get_ref(),
         title,
@@ -63,6 +133,8 @@ pub async fn put_lesson(
         daily.unwrap_or_default(),
         weekly.unwrap_or_default(),
         monthly.unwrap_or_default(),
+        exceptions.unwrap_or_default(),
+        teachers,
         &account_id,
     )
     .await?
@@ -85,6 +157,23 @@ pub struct LessonUpdateRequest {
     weekly: Option<Vec<WeeklyRepeat>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     monthly: Option<Vec<MonthlyRepeat>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exceptions: Option<Vec<RepeatException>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    teachers: Option<Vec<TeacherID>>,
+}
+
+impl LessonUpdateRequest {
+    pub fn validated(self) -> std::result::Result<Self, APIError> {
+        check_durations(
+            &self.singles,
+            &self.daily,
+            &self.weekly,
+            &self.monthly,
+            &self.exceptions,
+        )?;
+        Ok(self)
+    }
 }
 
 #[patch(
@@ -96,6 +185,7 @@ pub struct LessonUpdateRequest {
 pub async fn patch_lesson(
     db: web::Data<PgPool>,
     lesson_id: LessonID,
+    account_id: AccountID,
     patch: web::Json<LessonUpdateRequest>,
 ) -> std::result::Result<HttpResponse, APIError> {
     let LessonUpdateRequest {
@@ -104,8 +194,14 @@ pub async fn patch_lesson(
         daily,
         weekly,
         monthly,
+        exceptions,
+        teachers,
         description,
-    } = patch.into_inner();
+    } = patch.into_inner().validated()?;
+
+    if let Some(teachers) = &teachers {
+        check_teachers_access(db.get_ref(), &account_id, teachers).await?;
+    }
 
     Lesson::update(
         db.get_ref(),
@@ -115,6 +211,8 @@ pub async fn patch_lesson(
         &daily,
         &weekly,
         &monthly,
+        &exceptions,
+        &teachers,
         &description,
     )
     .await?;
@@ -123,7 +221,7 @@ pub async fn patch_lesson(
 
 #[delete(
     "/lesson/{id}",
-    wrap = "CheckPermission::<LessonPermission>::new(PermissionType::ReadWrite)",
+    wrap = "CheckPermission::<LessonPermission>::new(PermissionType::Owner)",
     wrap = "PathExtractor::<LessonID>::new()",
     wrap = "Authentication"
 )]
@@ -135,6 +233,47 @@ pub async fn delete_lesson(
     Ok(HttpResponse::NoContent().finish())
 }
 
+#[derive(Deserialize)]
+pub struct LessonTeacherRequest {
+    teacher_id: TeacherID,
+}
+
+#[post(
+    "/lesson/{id}/teachers",
+    wrap = "CheckPermission::<LessonPermission>::new(PermissionType::ReadWrite)",
+    wrap = "PathExtractor::<LessonID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn assign_teacher(
+    db: web::Data<PgPool>,
+    lesson_id: LessonID,
+    account_id: AccountID,
+    request: web::Json<LessonTeacherRequest>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let LessonTeacherRequest { teacher_id } = request.into_inner();
+
+    TeacherPermission::type_of_entity(db.get_ref(), &account_id, &teacher_id).await?;
+    Lesson::assign_teacher(db.get_ref(), &lesson_id, &teacher_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete(
+    "/lesson/{id}/teachers",
+    wrap = "CheckPermission::<LessonPermission>::new(PermissionType::ReadWrite)",
+    wrap = "PathExtractor::<LessonID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn unassign_teacher(
+    db: web::Data<PgPool>,
+    lesson_id: LessonID,
+    query: web::Query<LessonTeacherRequest>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let LessonTeacherRequest { teacher_id } = query.into_inner();
+
+    Lesson::unassign_teacher(db.get_ref(), &lesson_id, &teacher_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
 #[derive(Deserialize)]
 pub struct GetLessonsQuery {
     date: NaiveDate
@@ -150,10 +289,54 @@ pub async fn get_lessons(
     Ok(Lesson::for_date(db.get_ref(), &date, &account_id).await?.into())
 }
 
+#[derive(Deserialize)]
+pub struct GetCalendarQuery {
+    from: NaiveDate,
+    to: NaiveDate,
+}
+
+const MAX_CALENDAR_DAYS: i64 = 366;
+
+impl GetCalendarQuery {
+    pub fn validated(self) -> std::result::Result<(NaiveDate, NaiveDate), APIError> {
+        let GetCalendarQuery { from, to } = self;
+
+        if to < from || (to - from).num_days() >= MAX_CALENDAR_DAYS {
+            return Err(APIError::BadRequest {
+                message: format!(
+                    "`to` must not precede `from` and the range must not exceed {} days",
+                    MAX_CALENDAR_DAYS
+                ),
+                scope: Some(RequestScope::Query),
+            });
+        }
+
+        Ok((from, to))
+    }
+}
+
+#[get("/calendar", wrap = "Authentication")]
+pub async fn get_calendar(
+    db: web::Data<PgPool>,
+    query: web::Query<GetCalendarQuery>,
+    account_id: AccountID,
+) -> Result<Vec<Occurrence>> {
+    let (from, to) = query.into_inner().validated()?;
+
+    Ok(Occurrence::between(db.get_ref(), &from, &to, &None, &account_id)
+        .await?
+        .into())
+}
+
 pub fn configure_lesson_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(get_lesson)
         .service(put_lesson)
         .service(patch_lesson)
         .service(delete_lesson)
-        .service(get_lessons);
+        .service(get_lessons)
+        .service(get_calendar)
+        .service(assign_teacher)
+        .service(unassign_teacher);
+    configure_permission_routes::<LessonPermission>(cfg, "/lesson");
+    configure_group_permission_routes::<LessonPermission>(cfg, "/lesson");
 }
@@ -1,6 +1,12 @@
+pub mod admin;
 pub mod auth;
+pub mod group;
+pub mod invite;
 pub mod lesson;
+pub mod permission;
+pub mod task;
 pub mod teacher;
+pub mod totp;
 
 use actix_web::web;
 use actix_web::{get};
@@ -26,6 +32,11 @@ async fn serviceinfo() -> Payload<ServiceInfo<'static>> {
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(serviceinfo);
     auth::configure_auth_routes(cfg);
+    totp::configure_totp_routes(cfg);
     lesson::configure_lesson_routes(cfg);
-    teacher::configure_teacher_routes(cfg)
+    teacher::configure_teacher_routes(cfg);
+    task::configure_task_routes(cfg);
+    group::configure_group_routes(cfg);
+    invite::configure_invite_routes(cfg);
+    admin::configure_admin_routes(cfg)
 }
\ No newline at end of file
@@ -0,0 +1,316 @@
+use actix_web::{guard, web, HttpResponse};
+use chrono::NaiveDateTime;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlx::PgPool;
+use std::vec::Vec;
+
+use crate::error::{APIError, RequestScope, Result};
+use crate::keyring::Keyring;
+use crate::middleware::{Authentication, CheckPermission, PathExtractor};
+use crate::model::{
+    account::{Account, AccountID},
+    group::GroupID,
+    invite::{Invite, InviteID},
+    permission::{
+        EntityPermission, GroupEntityPermission, GroupGrant, GroupPermission, PermissionError,
+        PermissionHolder, PermissionType,
+    },
+};
+use crate::token::{ApplicationToken, InviteInfo, InviteToken};
+
+pub async fn list_permissions<T: EntityPermission>(
+    db: web::Data<PgPool>,
+    entity_id: T::EntityID,
+) -> Result<Vec<PermissionHolder>> {
+    Ok(T::list(db.get_ref(), &entity_id).await?.into())
+}
+
+#[derive(Deserialize)]
+pub struct GrantPermissionRequest {
+    login: String,
+    permission: PermissionType,
+}
+
+/// Looks up the account a permission is managed for. Accounts can't manage their own access
+async fn permission_target(
+    db: &PgPool,
+    login: String,
+    account_id: &AccountID,
+) -> std::result::Result<AccountID, APIError> {
+    let target = Account::get_by_login(db, login)
+        .await?
+        .ok_or(APIError::AccountDoesNotExist)?;
+
+    if target.id == *account_id {
+        return Err(APIError::BadRequest {
+            message: "Cannot change your own permission".to_string(),
+            scope: None,
+        });
+    }
+
+    Ok(target.id)
+}
+
+pub async fn grant_permission<T: EntityPermission>(
+    db: web::Data<PgPool>,
+    entity_id: T::EntityID,
+    account_id: AccountID,
+    request: web::Json<GrantPermissionRequest>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let GrantPermissionRequest { login, permission } = request.into_inner();
+    if permission == PermissionType::Owner {
+        return Err(APIError::BadRequest {
+            message: "Ownership can only be transferred".to_string(),
+            scope: Some(RequestScope::Body),
+        });
+    }
+    let target = permission_target(db.get_ref(), login, &account_id).await?;
+
+    T::grant(db.get_ref(), permission, &entity_id, &target).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct RevokePermissionQuery {
+    login: String,
+}
+
+pub async fn revoke_permission<T: EntityPermission>(
+    db: web::Data<PgPool>,
+    entity_id: T::EntityID,
+    account_id: AccountID,
+    query: web::Query<RevokePermissionQuery>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let RevokePermissionQuery { login } = query.into_inner();
+    let target = permission_target(db.get_ref(), login, &account_id).await?;
+
+    T::revoke(db.get_ref(), &entity_id, &target).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct TransferOwnershipRequest {
+    login: String,
+}
+
+pub async fn transfer_ownership<T: EntityPermission>(
+    db: web::Data<PgPool>,
+    entity_id: T::EntityID,
+    account_id: AccountID,
+    request: web::Json<TransferOwnershipRequest>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let TransferOwnershipRequest { login } = request.into_inner();
+    let target = permission_target(db.get_ref(), login, &account_id).await?;
+
+    T::transfer_ownership(db.get_ref(), &entity_id, &account_id, &target).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct CreateInviteRequest {
+    permission: PermissionType,
+    /// Unlimited when omitted
+    max_uses: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct InviteResponse {
+    id: InviteID,
+    token: InviteToken,
+    expires_at: NaiveDateTime,
+}
+
+pub async fn create_invite<T>(
+    db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    entity_id: T::EntityID,
+    account_id: AccountID,
+    request: web::Json<CreateInviteRequest>,
+) -> Result<InviteResponse>
+where
+    T: EntityPermission,
+    T::EntityID: Into<uuid::Uuid>,
+{
+    let CreateInviteRequest {
+        permission,
+        max_uses,
+    } = request.into_inner();
+    if permission == PermissionType::Owner {
+        return Err(APIError::BadRequest {
+            message: "Ownership can only be transferred".to_string(),
+            scope: Some(RequestScope::Body),
+        });
+    }
+    if let Some(max_uses) = max_uses {
+        if max_uses < 1 {
+            return Err(APIError::BadRequest {
+                message: "`max_uses` must be positive".to_string(),
+                scope: Some(RequestScope::Body),
+            });
+        }
+    }
+
+    let expires_at = chrono::Utc::now().naive_utc() + InviteToken::valid_for();
+    let id = Invite::create(db.get_ref(), &account_id, max_uses, &expires_at).await?;
+    let token = InviteToken::generate_token(&keyring, InviteInfo {
+        invite_id: id,
+        entity_type: T::ENTITY_TYPE,
+        entity_id: entity_id.into(),
+        permission,
+    })?;
+
+    Ok(InviteResponse {
+        id,
+        token,
+        expires_at,
+    }
+    .into())
+}
+
+/// Registers sharing routes under `{entity_path}/{id}/permissions`, `{entity_path}/{id}/owner`
+/// and `{entity_path}/{id}/invites`.
+/// Anyone who can read the entity may list permissions, managing them requires ownership
+pub fn configure_permission_routes<T>(cfg: &mut web::ServiceConfig, entity_path: &str)
+where
+    T: EntityPermission + 'static,
+    T::EntityID: DeserializeOwned + Into<uuid::Uuid> + 'static,
+{
+    let path = format!("{}/{{id}}/permissions", entity_path);
+    let owner_path = format!("{}/{{id}}/owner", entity_path);
+    let invites_path = format!("{}/{{id}}/invites", entity_path);
+
+    cfg.service(
+        web::resource(&path[..])
+            .guard(guard::Get())
+            .wrap(CheckPermission::<T>::new(PermissionType::Read))
+            .wrap(PathExtractor::<T::EntityID>::new())
+            .wrap(Authentication)
+            .to(list_permissions::<T>),
+    )
+    .service(
+        web::resource(&path[..])
+            .guard(guard::Post())
+            .wrap(CheckPermission::<T>::new(PermissionType::Owner))
+            .wrap(PathExtractor::<T::EntityID>::new())
+            .wrap(Authentication)
+            .to(grant_permission::<T>),
+    )
+    .service(
+        web::resource(&path[..])
+            .guard(guard::Delete())
+            .wrap(CheckPermission::<T>::new(PermissionType::Owner))
+            .wrap(PathExtractor::<T::EntityID>::new())
+            .wrap(Authentication)
+            .to(revoke_permission::<T>),
+    )
+    .service(
+        web::resource(&owner_path[..])
+            .guard(guard::Post())
+            .wrap(CheckPermission::<T>::new(PermissionType::Owner))
+            .wrap(PathExtractor::<T::EntityID>::new())
+            .wrap(Authentication)
+            .to(transfer_ownership::<T>),
+    )
+    .service(
+        web::resource(&invites_path[..])
+            .guard(guard::Post())
+            .wrap(CheckPermission::<T>::new(PermissionType::Owner))
+            .wrap(PathExtractor::<T::EntityID>::new())
+            .wrap(Authentication)
+            .to(create_invite::<T>),
+    );
+}
+
+pub async fn list_group_permissions<T: GroupEntityPermission>(
+    db: web::Data<PgPool>,
+    entity_id: T::EntityID,
+) -> Result<Vec<GroupGrant>> {
+    Ok(T::list_groups(db.get_ref(), &entity_id).await?.into())
+}
+
+#[derive(Deserialize)]
+pub struct GrantGroupPermissionRequest {
+    group_id: GroupID,
+    permission: PermissionType,
+}
+
+pub async fn grant_group_permission<T: GroupEntityPermission>(
+    db: web::Data<PgPool>,
+    entity_id: T::EntityID,
+    account_id: AccountID,
+    request: web::Json<GrantGroupPermissionRequest>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let GrantGroupPermissionRequest {
+        group_id,
+        permission,
+    } = request.into_inner();
+    if permission == PermissionType::Owner {
+        return Err(APIError::BadRequest {
+            message: "Groups can't own entities".to_string(),
+            scope: Some(RequestScope::Body),
+        });
+    }
+
+    // Only groups the account is a member of can be shared with
+    GroupPermission::type_of_entity(db.get_ref(), &account_id, &group_id)
+        .await
+        .map_err(|error| match error {
+            PermissionError::EntityNotPresent | PermissionError::PermissionNotPresent => {
+                APIError::GroupDoesNotExist
+            }
+            error => error.into(),
+        })?;
+
+    T::grant_to_group(db.get_ref(), permission, &entity_id, &group_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct RevokeGroupPermissionQuery {
+    group_id: GroupID,
+}
+
+pub async fn revoke_group_permission<T: GroupEntityPermission>(
+    db: web::Data<PgPool>,
+    entity_id: T::EntityID,
+    query: web::Query<RevokeGroupPermissionQuery>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let RevokeGroupPermissionQuery { group_id } = query.into_inner();
+
+    T::revoke_from_group(db.get_ref(), &entity_id, &group_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Registers routes sharing the entity with groups under `{entity_path}/{id}/groups`
+pub fn configure_group_permission_routes<T>(cfg: &mut web::ServiceConfig, entity_path: &str)
+where
+    T: GroupEntityPermission + 'static,
+    T::EntityID: DeserializeOwned + 'static,
+{
+    let path = format!("{}/{{id}}/groups", entity_path);
+
+    cfg.service(
+        web::resource(&path[..])
+            .guard(guard::Get())
+            .wrap(CheckPermission::<T>::new(PermissionType::Read))
+            .wrap(PathExtractor::<T::EntityID>::new())
+            .wrap(Authentication)
+            .to(list_group_permissions::<T>),
+    )
+    .service(
+        web::resource(&path[..])
+            .guard(guard::Post())
+            .wrap(CheckPermission::<T>::new(PermissionType::Owner))
+            .wrap(PathExtractor::<T::EntityID>::new())
+            .wrap(Authentication)
+            .to(grant_group_permission::<T>),
+    )
+    .service(
+        web::resource(&path[..])
+            .guard(guard::Delete())
+            .wrap(CheckPermission::<T>::new(PermissionType::Owner))
+            .wrap(PathExtractor::<T::EntityID>::new())
+            .wrap(Authentication)
+            .to(revoke_group_permission::<T>),
+    );
+}
@@ -0,0 +1,250 @@
+use actix_web::{delete, get, patch, put, web, HttpResponse};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+
+use crate::error::{APIError, RequestScope, Result};
+use crate::middleware::{Authentication, CheckPermission, PathExtractor};
+use crate::model::{
+    account::AccountID,
+    lesson::{Lesson, LessonID},
+    permission::{EntityPermission, LessonPermission, PermissionType, TaskPermission},
+    task::{Task, TaskCompletion, TaskID, TaskProgress},
+};
+use crate::payload::Payload;
+use crate::routes::permission::configure_permission_routes;
+use crate::util::deserialize_optional_field;
+
+#[get(
+    "/task/{id}",
+    wrap = "CheckPermission::<TaskPermission>::new(PermissionType::Read)",
+    wrap = "PathExtractor::<TaskID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn get_task(
+    db: web::Data<PgPool>,
+    task_id: TaskID,
+    account_id: AccountID,
+) -> Result<Task> {
+    Task::by_id(db.get_ref(), task_id, &account_id)
+        .await?
+        .ok_or(APIError::TaskDoesNotExist)
+        .map(Payload::from)
+}
+
+#[derive(Deserialize)]
+pub struct TaskCreateRequest {
+    name: String,
+    description: Option<String>,
+    lesson_id: Option<LessonID>,
+    /// Defaults to the next occurrence of the lesson when omitted
+    lesson_date: Option<NaiveDate>,
+}
+
+#[put("/task", wrap = "Authentication")]
+pub async fn put_task(
+    db: web::Data<PgPool>,
+    task: web::Json<TaskCreateRequest>,
+    account_id: AccountID,
+) -> Result<Task> {
+    let TaskCreateRequest {
+        name,
+        description,
+        lesson_id,
+        lesson_date,
+    } = task.into_inner();
+
+    if let Some(lesson_id) = &lesson_id {
+        LessonPermission::type_of_entity(db.get_ref(), &account_id, lesson_id).await?;
+    }
+
+    let lesson_date = match (lesson_date, &lesson_id) {
+        (Some(lesson_date), _) => lesson_date,
+        (None, Some(lesson_id)) => {
+            // Lesson times are wall-clock times, so they are compared with the local time of the server
+            let now = chrono::Local::now().naive_local();
+            Lesson::by_id(db.get_ref(), *lesson_id)
+                .await?
+                .ok_or(APIError::LessonDosNotExist)?
+                .next_occurrence_from(&now)
+                .map(|occurrence| occurrence.starts_at.date())
+                .ok_or(APIError::BadRequest {
+                    message: "Lesson has no upcoming occurrences".to_string(),
+                    scope: Some(RequestScope::Body),
+                })?
+        }
+        (None, None) => {
+            return Err(APIError::BadRequest {
+                message: "`lesson_date` is required when `lesson_id` is not present".to_string(),
+                scope: Some(RequestScope::Body),
+            })
+        }
+    };
+
+    Ok(Task::create(
+        db.get_ref(),
+        name,
+        description,
+        lesson_id,
+        lesson_date,
+        &account_id,
+    )
+    .await?
+    .into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TaskUpdateRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(deserialize_with = "deserialize_optional_field")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<Option<String>>,
+    #[serde(deserialize_with = "deserialize_optional_field")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lesson_id: Option<Option<LessonID>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lesson_date: Option<NaiveDate>,
+}
+
+#[patch(
+    "/task/{id}",
+    wrap = "CheckPermission::<TaskPermission>::new(PermissionType::ReadWrite)",
+    wrap = "PathExtractor::<TaskID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn patch_task(
+    db: web::Data<PgPool>,
+    task_id: TaskID,
+    account_id: AccountID,
+    patch: web::Json<TaskUpdateRequest>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let TaskUpdateRequest {
+        name,
+        description,
+        lesson_id,
+        lesson_date,
+    } = patch.into_inner();
+
+    if let Some(Some(lesson_id)) = &lesson_id {
+        LessonPermission::type_of_entity(db.get_ref(), &account_id, lesson_id).await?;
+    }
+
+    Task::update(
+        db.get_ref(),
+        &task_id,
+        name,
+        description,
+        lesson_id,
+        lesson_date,
+    )
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete(
+    "/task/{id}",
+    wrap = "CheckPermission::<TaskPermission>::new(PermissionType::Owner)",
+    wrap = "PathExtractor::<TaskID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn delete_task(
+    db: web::Data<PgPool>,
+    task_id: TaskID,
+) -> std::result::Result<HttpResponse, APIError> {
+    Task::delete(db.get_ref(), &task_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct GetTasksQuery {
+    from: NaiveDate,
+    to: Option<NaiveDate>,
+}
+
+#[get("/tasks", wrap = "Authentication")]
+pub async fn get_tasks(
+    db: web::Data<PgPool>,
+    query: web::Query<GetTasksQuery>,
+    account_id: AccountID,
+) -> Result<BTreeMap<NaiveDate, Vec<Task>>> {
+    let GetTasksQuery { from, to } = query.into_inner();
+    let to = to.unwrap_or(from);
+
+    if to < from {
+        return Err(APIError::BadRequest {
+            message: "`to` must not precede `from`".to_string(),
+            scope: Some(RequestScope::Query),
+        });
+    }
+
+    let mut agenda = BTreeMap::<NaiveDate, Vec<Task>>::new();
+    for task in Task::for_date_range(db.get_ref(), &from, &to, &account_id).await? {
+        agenda.entry(task.lesson_date).or_default().push(task);
+    }
+
+    Ok(agenda.into())
+}
+
+#[put(
+    "/task/{id}/completion",
+    wrap = "CheckPermission::<TaskPermission>::new(PermissionType::Read)",
+    wrap = "PathExtractor::<TaskID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn complete_task(
+    db: web::Data<PgPool>,
+    task_id: TaskID,
+    account_id: AccountID,
+) -> Result<TaskCompletion> {
+    Ok(Task::complete(db.get_ref(), &task_id, &account_id)
+        .await?
+        .into())
+}
+
+#[delete(
+    "/task/{id}/completion",
+    wrap = "CheckPermission::<TaskPermission>::new(PermissionType::Read)",
+    wrap = "PathExtractor::<TaskID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn uncomplete_task(
+    db: web::Data<PgPool>,
+    task_id: TaskID,
+    account_id: AccountID,
+) -> std::result::Result<HttpResponse, APIError> {
+    Task::uncomplete(db.get_ref(), &task_id, &account_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct GetTaskProgressQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[get("/tasks/progress", wrap = "Authentication")]
+pub async fn get_task_progress(
+    db: web::Data<PgPool>,
+    query: web::Query<GetTaskProgressQuery>,
+    account_id: AccountID,
+) -> Result<Vec<TaskProgress>> {
+    let GetTaskProgressQuery { from, to } = query.into_inner();
+    Ok(Task::progress(db.get_ref(), &from, &to, &account_id)
+        .await?
+        .into())
+}
+
+pub fn configure_task_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_task)
+        .service(put_task)
+        .service(patch_task)
+        .service(delete_task)
+        .service(get_tasks)
+        .service(complete_task)
+        .service(uncomplete_task)
+        .service(get_task_progress);
+    configure_permission_routes::<TaskPermission>(cfg, "/task");
+}
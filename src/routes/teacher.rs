@@ -1,15 +1,21 @@
-use actix_web::{delete, get, patch, put, web, HttpResponse};
+use actix_web::{delete, get, patch, post, put, web, HttpResponse};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 
 use crate::error::{APIError, Result};
 use crate::middleware::{Authentication, CheckPermission, PathExtractor};
 use crate::model::{
-    account::AccountID,
+    account::{Account, AccountID},
+    calendar::Occurrence,
+    lesson::Lesson,
     permission::{PermissionType, TeacherPermission},
-    teacher::{Teacher, TeacherID},
+    teacher::{AffiliationInvite, Teacher, TeacherID},
 };
 use crate::payload::Payload;
+use crate::routes::lesson::GetCalendarQuery;
+use crate::routes::permission::{
+    configure_group_permission_routes, configure_permission_routes,
+};
 use crate::util::deserialize_optional_field;
 
 #[get(
@@ -46,7 +52,7 @@ pub async fn put_teacher(
         db.get_ref(),
         first_name,
         last_name,
-        None, // TODO: add user affiliation
+        None, // Accounts claim teachers through affiliation invites
         &account_id,
     )
     .await?
@@ -85,7 +91,7 @@ pub async fn patch_teacher(
 
 #[delete(
     "/teacher/{id}",
-    wrap = "CheckPermission::<TeacherPermission>::new(PermissionType::ReadWrite)",
+    wrap = "CheckPermission::<TeacherPermission>::new(PermissionType::Owner)",
     wrap = "PathExtractor::<TeacherID>::new()",
     wrap = "Authentication"
 )]
@@ -102,10 +108,120 @@ pub async fn get_teachers(db: web::Data<PgPool>, account_id: AccountID) -> Resul
     Ok(Teacher::of_user(db.get_ref(), &account_id).await?.into())
 }
 
+#[get(
+    "/teacher/{id}/lessons",
+    wrap = "CheckPermission::<TeacherPermission>::new(PermissionType::Read)",
+    wrap = "PathExtractor::<TeacherID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn get_teacher_lessons(
+    db: web::Data<PgPool>,
+    teacher_id: TeacherID,
+    account_id: AccountID,
+) -> Result<Vec<Lesson>> {
+    Ok(Lesson::of_teacher(db.get_ref(), &teacher_id, &account_id)
+        .await?
+        .into())
+}
+
+#[get(
+    "/teacher/{id}/calendar",
+    wrap = "CheckPermission::<TeacherPermission>::new(PermissionType::Read)",
+    wrap = "PathExtractor::<TeacherID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn get_teacher_calendar(
+    db: web::Data<PgPool>,
+    teacher_id: TeacherID,
+    account_id: AccountID,
+    query: web::Query<GetCalendarQuery>,
+) -> Result<Vec<Occurrence>> {
+    let (from, to) = query.into_inner().validated()?;
+
+    Ok(
+        Occurrence::between(db.get_ref(), &from, &to, &Some(teacher_id), &account_id)
+            .await?
+            .into(),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct AffiliationInviteRequest {
+    login: String,
+}
+
+#[post(
+    "/teacher/{id}/affiliation",
+    wrap = "CheckPermission::<TeacherPermission>::new(PermissionType::ReadWrite)",
+    wrap = "PathExtractor::<TeacherID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn invite_affiliate(
+    db: web::Data<PgPool>,
+    teacher_id: TeacherID,
+    request: web::Json<AffiliationInviteRequest>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let AffiliationInviteRequest { login } = request.into_inner();
+    let account = Account::get_by_login(db.get_ref(), login)
+        .await?
+        .ok_or(APIError::AccountDoesNotExist)?;
+
+    Teacher::invite_affiliate(db.get_ref(), &teacher_id, &account.id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post(
+    "/teacher/{id}/affiliation/accept",
+    wrap = "PathExtractor::<TeacherID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn accept_affiliation(
+    db: web::Data<PgPool>,
+    teacher_id: TeacherID,
+    account_id: AccountID,
+) -> std::result::Result<HttpResponse, APIError> {
+    if !Teacher::accept_affiliation(db.get_ref(), &teacher_id, &account_id).await? {
+        return Err(APIError::InvitationDoesNotExist);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete(
+    "/teacher/{id}/affiliation",
+    wrap = "CheckPermission::<TeacherPermission>::new(PermissionType::ReadWrite)",
+    wrap = "PathExtractor::<TeacherID>::new()",
+    wrap = "Authentication"
+)]
+pub async fn remove_affiliation(
+    db: web::Data<PgPool>,
+    teacher_id: TeacherID,
+) -> std::result::Result<HttpResponse, APIError> {
+    Teacher::remove_affiliation(db.get_ref(), &teacher_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/affiliations", wrap = "Authentication")]
+pub async fn get_affiliation_invites(
+    db: web::Data<PgPool>,
+    account_id: AccountID,
+) -> Result<Vec<AffiliationInvite>> {
+    Ok(Teacher::affiliation_invites(db.get_ref(), &account_id)
+        .await?
+        .into())
+}
+
 pub fn configure_teacher_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(get_teacher)
         .service(put_teacher)
         .service(patch_teacher)
         .service(delete_teacher)
-        .service(get_teachers);
+        .service(get_teachers)
+        .service(get_teacher_lessons)
+        .service(get_teacher_calendar)
+        .service(invite_affiliate)
+        .service(accept_affiliation)
+        .service(remove_affiliation)
+        .service(get_affiliation_invites);
+    configure_permission_routes::<TeacherPermission>(cfg, "/teacher");
+    configure_group_permission_routes::<TeacherPermission>(cfg, "/teacher");
 }
@@ -0,0 +1,186 @@
+use actix_web::{delete, post, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::error::{APIError, Result};
+use crate::keyring::Keyring;
+use crate::middleware::Authentication;
+use crate::model::{
+    account::{Account, AccountID},
+    totp::Totp,
+};
+use crate::routes::auth::{ensure_totp_not_locked, record_totp_failure};
+use crate::totp;
+use crate::types::RedisPool;
+
+#[derive(Serialize)]
+pub struct TotpEnrollment {
+    /// Base32 encoded, for authenticators the URI cannot be scanned into
+    secret: String,
+    uri: String,
+}
+
+#[derive(Serialize)]
+pub struct RecoveryCodes {
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TotpCodeRequest {
+    code: String,
+}
+
+fn already_enabled() -> APIError {
+    APIError::BadRequest {
+        message: "Two-factor authentication is already enabled".to_string(),
+        scope: None,
+    }
+}
+
+fn generate_recovery_codes() -> std::result::Result<(Vec<String>, Vec<String>), APIError> {
+    let codes = totp::generate_recovery_codes()?;
+    let hashes = codes
+        .iter()
+        .map(|code| totp::hash_recovery_code(code))
+        .collect();
+    Ok((codes, hashes))
+}
+
+/// Starts over any unconfirmed enrollment. Nothing changes for logging in until it is confirmed
+#[post("/auth/totp", wrap = "Authentication")]
+pub async fn enroll_totp(
+    db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    account_id: AccountID,
+) -> Result<TotpEnrollment> {
+    let account = Account::by_id(db.get_ref(), &account_id)
+        .await?
+        .ok_or(APIError::AccountDoesNotExist)?;
+
+    let secret = totp::generate_secret()?;
+    let sealed_secret = keyring.seal_totp_secret(&account_id, &secret)?;
+    if !Totp::enroll(db.get_ref(), &account_id, &sealed_secret).await? {
+        return Err(already_enabled());
+    }
+
+    Ok(TotpEnrollment {
+        secret: totp::base32_encode(&secret),
+        uri: totp::provisioning_uri(&secret, &account.login),
+    }
+    .into())
+}
+
+/// Enables two-factor authentication once the authenticator proves to produce valid codes.
+/// Recovery codes are only ever shown in the response
+#[post("/auth/totp/confirm", wrap = "Authentication")]
+pub async fn confirm_totp(
+    db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    redis_pool: web::Data<RedisPool>,
+    account_id: AccountID,
+    request: web::Json<TotpCodeRequest>,
+) -> Result<RecoveryCodes> {
+    let TotpCodeRequest { code } = request.into_inner();
+    let totp = Totp::by_account(db.get_ref(), &account_id)
+        .await?
+        .ok_or(APIError::BadRequest {
+            message: "Two-factor authentication enrollment has not been started".to_string(),
+            scope: None,
+        })?;
+    if totp.confirmed_at.is_some() {
+        return Err(already_enabled());
+    }
+
+    // Wrong codes count towards the same lockout as logins do
+    let mut redis = redis_pool.get().await?;
+    ensure_totp_not_locked(&mut redis, &account_id).await?;
+    let step = totp::matching_step(
+        &keyring.unseal_totp_secret(&account_id, &totp.sealed_secret)?,
+        &code,
+        totp::current_step(),
+        totp.last_used_step,
+    )?;
+    let (recovery_codes, hashes) = generate_recovery_codes()?;
+    let confirmed = match step {
+        Some(step) => Totp::confirm(db.get_ref(), &account_id, step, &hashes).await?,
+        None => false,
+    };
+    if !confirmed {
+        record_totp_failure(&mut redis, &account_id).await?;
+        return Err(APIError::InvalidTotpCode);
+    }
+
+    Ok(RecoveryCodes { recovery_codes }.into())
+}
+
+/// Replaces all recovery codes, used or not
+#[post("/auth/totp/recovery-codes", wrap = "Authentication")]
+pub async fn regenerate_recovery_codes(
+    db: web::Data<PgPool>,
+    keyring: web::Data<Keyring>,
+    redis_pool: web::Data<RedisPool>,
+    account_id: AccountID,
+    request: web::Json<TotpCodeRequest>,
+) -> Result<RecoveryCodes> {
+    let TotpCodeRequest { code } = request.into_inner();
+    let totp = Totp::by_account(db.get_ref(), &account_id)
+        .await?
+        .filter(|totp| totp.confirmed_at.is_some())
+        .ok_or(APIError::BadRequest {
+            message: "Two-factor authentication is not enabled".to_string(),
+            scope: None,
+        })?;
+
+    let mut redis = redis_pool.get().await?;
+    ensure_totp_not_locked(&mut redis, &account_id).await?;
+    let step = totp::matching_step(
+        &keyring.unseal_totp_secret(&account_id, &totp.sealed_secret)?,
+        &code,
+        totp::current_step(),
+        totp.last_used_step,
+    )?;
+    let accepted = match step {
+        Some(step) => Totp::use_step(db.get_ref(), &account_id, step).await?,
+        None => false,
+    };
+    if !accepted {
+        record_totp_failure(&mut redis, &account_id).await?;
+        return Err(APIError::InvalidTotpCode);
+    }
+
+    let (recovery_codes, hashes) = generate_recovery_codes()?;
+    Totp::replace_recovery_codes(db.get_ref(), &account_id, &hashes).await?;
+
+    Ok(RecoveryCodes { recovery_codes }.into())
+}
+
+#[derive(Deserialize)]
+pub struct DisableTotpRequest {
+    password: String,
+}
+
+#[delete("/auth/totp", wrap = "Authentication")]
+pub async fn disable_totp(
+    db: web::Data<PgPool>,
+    account_id: AccountID,
+    request: web::Json<DisableTotpRequest>,
+) -> std::result::Result<HttpResponse, APIError> {
+    let DisableTotpRequest { password } = request.into_inner();
+    let account = Account::by_id(db.get_ref(), &account_id)
+        .await?
+        .ok_or(APIError::AccountDoesNotExist)?;
+
+    if !bcrypt::verify(password, &account.password_hash[..])? {
+        return Err(APIError::InvalidCredentials);
+    }
+
+    Totp::disable(db.get_ref(), &account_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn configure_totp_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(enroll_totp)
+        .service(confirm_totp)
+        .service(regenerate_recovery_codes)
+        .service(disable_totp);
+}
@@ -1,16 +1,19 @@
 use actix_http::http::HeaderMap;
 use chrono::Duration;
-use hmac::{Hmac, NewMac};
-use jwt::{SignWithKey, VerifyWithKey};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use sha2::Sha256;
-use std::env;
 use std::vec::Vec;
 use thiserror::Error;
 use futures::future::{ready, Ready};
 
 use crate::error::{APIError, RequestScope};
-use crate::model::account::AccountID;
+use crate::keyring::Keyring;
+use redis::AsyncCommands;
+use crate::model::{
+    account::AccountID,
+    invite::InviteID,
+    session::SessionID,
+    permission::{EntityType, PermissionType},
+};
 use crate::uuid_wrapper;
 
 pub type SecondsSinceEpoch = i64;
@@ -51,6 +54,12 @@ pub struct ApplicationClaim<T> {
 
 uuid_wrapper!(RefreshTokenID);
 
+impl RefreshTokenID {
+    pub fn generate() -> Self {
+        Self(uuid::Uuid::new_v4())
+    }
+}
+
 impl<T> ApplicationClaim<T> {
     pub fn new(inner: T, valid_for: Duration) -> Result<ApplicationClaim<T>, InvalidDuration> {
         let now = chrono::Utc::now();
@@ -97,6 +106,41 @@ pub struct AccessTokenInfo {
 pub struct RefreshTokenInfo {
     pub account_id: AccountID,
     pub token_id: RefreshTokenID,
+    /// Family of refresh tokens the token was rotated within
+    pub session_id: SessionID,
+}
+
+/// Access shared by an invite link
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct InviteInfo {
+    pub invite_id: InviteID,
+    pub entity_type: EntityType,
+    pub entity_id: uuid::Uuid,
+    pub permission: PermissionType,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PasswordResetInfo {
+    pub account_id: AccountID,
+    /// Single use of the token is enforced by removing the id from Redis
+    pub reset_id: uuid::Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct EmailConfirmationInfo {
+    pub account_id: AccountID,
+    /// Address mailed the token, set on the account once confirmed
+    pub email: String,
+    /// Single use of the token is enforced by removing the id from Redis
+    pub confirmation_id: uuid::Uuid,
+}
+
+/// Password check passed, the login is finished with a one-time password
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TotpChallengeInfo {
+    pub account_id: AccountID,
+    /// Attempts left for the challenge are kept in Redis under the id
+    pub challenge_id: uuid::Uuid,
 }
 
 pub trait ApplicationToken: for <'de> Deserialize<'de> + Serialize + From<String> {
@@ -105,20 +149,14 @@ pub trait ApplicationToken: for <'de> Deserialize<'de> + Serialize + From<String
     fn valid_for() -> Duration;
     fn str_ref(&self) -> &str;
 
-    fn generate_token(info: Self::Claim) -> Result<Self, APIError> {
-        let token_secret: String = env::var("TOKEN_SECRET").expect("TOKEN_SECRET is not set");
-        let token_key: Hmac<Sha256> = Hmac::new_varkey(&token_secret.into_bytes()[..]).unwrap();
+    fn generate_token(keyring: &Keyring, info: Self::Claim) -> Result<Self, APIError> {
         let claim = ApplicationClaim::new(info, Self::valid_for())?;
-        let token = claim.sign_with_key(&token_key)?;
+        let token = keyring.sign(&claim)?;
         Ok(token.into())
     }
 
-    fn authenticate_claim(&self) -> Result<ApplicationClaim<Self::Claim>, APIError> {
-        let token_secret: String = env::var("TOKEN_SECRET").expect("TOKEN_SECRET is not set");
-        let token_key: Hmac<Sha256> = Hmac::new_varkey(&token_secret.into_bytes()[..]).unwrap();
-        let claim: ApplicationClaim<Self::Claim> = self.str_ref()
-            .verify_with_key(&token_key)
-            .map_err(|_| APIError::InvalidToken)?;
+    fn authenticate_claim(&self, keyring: &Keyring) -> Result<ApplicationClaim<Self::Claim>, APIError> {
+        let claim: ApplicationClaim<Self::Claim> = keyring.verify(self.str_ref())?;
         let now = chrono::Utc::now().timestamp();
         if claim.registered.expiration < now {
             return Err(APIError::TokenExpired)
@@ -164,6 +202,78 @@ impl ApplicationToken for RefreshToken {
     fn str_ref(&self) -> &str { &self.0[..] }
 }
 
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct InviteToken(String);
+
+impl From<String> for InviteToken {
+    fn from(str: String) -> Self {
+        Self(str)
+    }
+}
+
+impl ApplicationToken for InviteToken {
+    type Claim = InviteInfo;
+    fn valid_for() -> Duration { Duration::days(7) }
+    fn str_ref(&self) -> &str { &self.0[..] }
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct PasswordResetToken(String);
+
+impl std::fmt::Display for PasswordResetToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<String> for PasswordResetToken {
+    fn from(str: String) -> Self {
+        Self(str)
+    }
+}
+
+impl ApplicationToken for PasswordResetToken {
+    type Claim = PasswordResetInfo;
+    fn valid_for() -> Duration { Duration::minutes(30) }
+    fn str_ref(&self) -> &str { &self.0[..] }
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct EmailConfirmationToken(String);
+
+impl std::fmt::Display for EmailConfirmationToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<String> for EmailConfirmationToken {
+    fn from(str: String) -> Self {
+        Self(str)
+    }
+}
+
+impl ApplicationToken for EmailConfirmationToken {
+    type Claim = EmailConfirmationInfo;
+    fn valid_for() -> Duration { Duration::days(1) }
+    fn str_ref(&self) -> &str { &self.0[..] }
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct TotpChallengeToken(String);
+
+impl From<String> for TotpChallengeToken {
+    fn from(str: String) -> Self {
+        Self(str)
+    }
+}
+
+impl ApplicationToken for TotpChallengeToken {
+    type Claim = TotpChallengeInfo;
+    fn valid_for() -> Duration { Duration::minutes(5) }
+    fn str_ref(&self) -> &str { &self.0[..] }
+}
+
 #[derive(Error, Debug)]
 #[error("Invalid token validity duration. Datetime overflow")]
 pub struct InvalidDuration {}
@@ -176,28 +286,120 @@ impl From<InvalidDuration> for APIError {
     }
 }
 
-pub fn generate_token_pair(account_id: AccountID) -> Result<(AccessToken, RefreshToken), APIError> {
-    let token_id = RefreshTokenID(uuid::Uuid::new_v4());
-    let refresh_token = RefreshToken::generate_token(RefreshTokenInfo { account_id, token_id })?;
-    let access_token = AccessToken::generate_token(AccessTokenInfo { account_id, token_origin: token_id })?;
+pub fn generate_token_pair(
+    keyring: &Keyring,
+    account_id: AccountID,
+    session_id: SessionID,
+    token_id: RefreshTokenID,
+) -> Result<(AccessToken, RefreshToken), APIError> {
+    let refresh_token = RefreshToken::generate_token(keyring, RefreshTokenInfo { account_id, token_id, session_id })?;
+    let access_token = AccessToken::generate_token(keyring, AccessTokenInfo { account_id, token_origin: token_id })?;
     return Ok((access_token, refresh_token));
 }
 
-pub fn authenticate_claim(AccessToken(token): AccessToken) -> Result<ApplicationClaim<AccessTokenInfo>, APIError> {
-    let token_secret: String = env::var("TOKEN_SECRET").expect("TOKEN_SECRET is not set");
-    let token_key: Hmac<Sha256> = Hmac::new_varkey(&token_secret.into_bytes()[..]).unwrap();
-    let claim: ApplicationClaim<AccessTokenInfo> = token
-        .verify_with_key(&token_key)
-        .map_err(|_| APIError::InvalidToken)?;
-    let now = chrono::Utc::now().timestamp();
-    if claim.registered.expiration < now {
-        return Err(APIError::TokenExpired)
+
+/// Redis key marking every token issued from the refresh token as revoked
+pub fn revoked_token_key(account_id: &AccountID, token_id: &RefreshTokenID) -> String {
+    format!("revoked_token:{}:{}", account_id, token_id)
+}
+
+/// Redis key marking every token of the account as revoked
+pub fn deleted_user_key(account_id: &AccountID) -> String {
+    format!("deleted_user:{}", account_id)
+}
+
+/// Redis key present until the password reset is used or expires
+pub fn password_reset_key(reset_id: &uuid::Uuid) -> String {
+    format!("password_reset:{}", reset_id)
+}
+
+/// Redis key present until the email confirmation is used or expires
+pub fn email_confirmation_key(confirmation_id: &uuid::Uuid) -> String {
+    format!("email_confirmation:{}", confirmation_id)
+}
+
+/// Redis key counting recent failed one-time passwords of the account, across all its login challenges
+pub fn totp_failures_key(account_id: &AccountID) -> String {
+    format!("totp_failures:{}", account_id)
+}
+
+/// Redis key holding the number of codes that may still be tried for the login challenge
+pub fn totp_challenge_key(challenge_id: &uuid::Uuid) -> String {
+    format!("totp_challenge:{}", challenge_id)
+}
+
+/// Redis key holding the second up to which tokens of the account are no longer accepted
+pub fn tokens_valid_after_key(account_id: &AccountID) -> String {
+    format!("tokens_valid_after:{}", account_id)
+}
+
+pub async fn ensure_not_revoked(
+    redis: &mut deadpool_redis::Connection,
+    account_id: &AccountID,
+    token_id: &RefreshTokenID,
+    issued_at: Option<SecondsSinceEpoch>,
+) -> Result<(), APIError> {
+    let revoked: Option<u8> = redis.get(revoked_token_key(account_id, token_id)).await?;
+    if let Some(1) = revoked {
+        return Err(APIError::TokenRevoked);
     }
-    Ok(claim)
+
+    let revoked_user: Option<u8> = redis.get(deleted_user_key(account_id)).await?;
+    if let Some(1) = revoked_user {
+        return Err(APIError::TokenRevoked);
+    }
+
+    let valid_after: Option<SecondsSinceEpoch> =
+        redis.get(tokens_valid_after_key(account_id)).await?;
+    if let Some(valid_after) = valid_after {
+        if issued_at.map_or(true, |issued_at| issued_at <= valid_after) {
+            return Err(APIError::TokenRevoked);
+        }
+    }
+
+    Ok(())
+}
+
+/// Rejects the refresh token and every access token issued from it.
+/// The key is kept until `expires_at`, the moment the last of them dies on its own
+pub async fn revoke_refresh_token(
+    redis: &mut deadpool_redis::Connection,
+    account_id: &AccountID,
+    token_id: &RefreshTokenID,
+    expires_at: SecondsSinceEpoch,
+) -> Result<(), APIError> {
+    let ttl = expires_at - chrono::Utc::now().timestamp();
+    if ttl > 0 {
+        redis
+            .set_ex::<_, _, ()>(revoked_token_key(account_id, token_id), 1_u8, ttl as usize)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Rejects every token of the account issued before now or within the current second, token timestamps
+/// are whole seconds. Returns once that second is over, so tokens issued afterwards are accepted.
+/// The key outlives the longest living token, so it expires once it has nothing left to reject
+pub async fn revoke_tokens_issued_until_now(
+    redis: &mut deadpool_redis::Connection,
+    account_id: &AccountID,
+) -> Result<(), APIError> {
+    let now = chrono::Utc::now();
+    let ttl = RefreshToken::valid_for().num_seconds();
+    redis
+        .set_ex::<_, _, ()>(tokens_valid_after_key(account_id), now.timestamp(), ttl as usize)
+        .await?;
+
+    let rest_of_second = 1_000_000_000 - u64::from(now.timestamp_subsec_nanos().min(999_999_999));
+    actix_rt::time::delay_for(std::time::Duration::from_nanos(rest_of_second)).await;
+    Ok(())
 }
 
-pub fn authenticate_claim_from_headers(headers: &HeaderMap) -> Result<ApplicationClaim<AccessTokenInfo>, APIError> {
-    extract_token(headers).and_then(authenticate_claim)
+pub fn authenticate_claim_from_headers(
+    keyring: &Keyring,
+    headers: &HeaderMap,
+) -> Result<ApplicationClaim<AccessTokenInfo>, APIError> {
+    extract_token(headers).and_then(|token| token.authenticate_claim(keyring))
 }
 
 pub fn extract_token(headers: &HeaderMap) -> Result<AccessToken, APIError> {
@@ -0,0 +1,240 @@
+//! RFC 6238 time-based one-time passwords with the parameters authenticator apps default to:
+//! HMAC-SHA1, 6 digits and 30 second steps
+use openssl::{error::ErrorStack, hash::MessageDigest, pkey::PKey, rand::rand_bytes, sign::Signer};
+use sha2::{Digest, Sha256};
+
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+const SECRET_LENGTH: usize = 20;
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Steps a code is accepted in around the current one, covering clock drift of the device
+const ALLOWED_DRIFT: i64 = 1;
+const ISSUER: &str = "Ne Student";
+
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Unpadded RFC 4648 base32, the format authenticator apps take secrets in
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() * 8 + 4) / 5);
+    let mut buffer = 0_u16;
+    let mut bits = 0_u8;
+    for byte in bytes {
+        buffer = (buffer << 8) | u16::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[usize::from((buffer >> bits) & 0x1f)] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)] as char);
+    }
+    encoded
+}
+
+/// Keeps unreserved characters as is, as URIs expect
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+pub fn generate_secret() -> Result<Vec<u8>, ErrorStack> {
+    let mut secret = vec![0; SECRET_LENGTH];
+    rand_bytes(&mut secret)?;
+    Ok(secret)
+}
+
+/// `otpauth` URI authenticator apps enroll with, usually shown as a QR code
+pub fn provisioning_uri(secret: &[u8], account_name: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(ISSUER),
+        percent_encode(account_name),
+        base32_encode(secret),
+        percent_encode(ISSUER),
+        DIGITS,
+        STEP_SECONDS
+    )
+}
+
+pub fn current_step() -> i64 {
+    chrono::Utc::now().timestamp() / STEP_SECONDS
+}
+
+/// RFC 4226 HOTP value for the counter
+fn code_at(secret: &[u8], step: i64) -> Result<u32, ErrorStack> {
+    let key = PKey::hmac(secret)?;
+    let mut signer = Signer::new(MessageDigest::sha1(), &key)?;
+    signer.update(&step.to_be_bytes())?;
+    let hmac = signer.sign_to_vec()?;
+
+    let offset = usize::from(hmac[hmac.len() - 1] & 0x0f);
+    let truncated = u32::from_be_bytes([
+        hmac[offset] & 0x7f,
+        hmac[offset + 1],
+        hmac[offset + 2],
+        hmac[offset + 3],
+    ]);
+    Ok(truncated % 10_u32.pow(DIGITS))
+}
+
+/// Step the code belongs to, if it is valid around `step` and newer than `last_used_step`.
+/// Rejecting used steps here only spares a database round trip, `Totp::use_step` has the final say
+pub fn matching_step(
+    secret: &[u8],
+    code: &str,
+    step: i64,
+    last_used_step: Option<i64>,
+) -> Result<Option<i64>, ErrorStack> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Ok(None);
+    }
+    let code: u32 = code.parse().unwrap_or_default();
+
+    let mut matching = None;
+    // Every candidate is computed so the response time does not tell which one matched
+    for candidate in (step - ALLOWED_DRIFT)..=(step + ALLOWED_DRIFT) {
+        // `None` is less than any step, so nothing is used before the first login
+        let unused = last_used_step < Some(candidate);
+        if code_at(secret, candidate)? == code && unused && matching.is_none() {
+            matching = Some(candidate);
+        }
+    }
+    Ok(matching)
+}
+
+/// Single use codes letting the account in when the authenticator is lost, formatted as `xxxx-xxxx`
+pub fn generate_recovery_codes() -> Result<Vec<String>, ErrorStack> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0_u8; 5];
+            rand_bytes(&mut bytes)?;
+            let code = base32_encode(&bytes).to_lowercase();
+            Ok(format!("{}-{}", &code[..4], &code[4..]))
+        })
+        .collect()
+}
+
+/// Recovery codes carry enough entropy for a fast hash. Case and separators are ignored
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Secret of the RFC 6238 appendix B SHA-1 test vectors
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    fn step_of(time: i64) -> i64 {
+        time / STEP_SECONDS
+    }
+
+    #[test]
+    fn codes_match_rfc_6238_vectors() {
+        // Last six of the eight digits given in the RFC
+        let vectors = [
+            (59, 287_082),
+            (1_111_111_109, 81_804),
+            (1_111_111_111, 50_471),
+            (1_234_567_890, 5_924),
+            (2_000_000_000, 279_037),
+            (20_000_000_000, 353_130),
+        ];
+        for (time, code) in vectors.iter() {
+            assert_eq!(
+                code_at(SECRET, step_of(*time)).unwrap(),
+                *code,
+                "T = {}",
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn base32_matches_rfc_4648_vectors() {
+        // Padding is left out
+        let vectors = [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ];
+        for (input, encoded) in vectors.iter() {
+            assert_eq!(base32_encode(input.as_bytes()), *encoded);
+        }
+    }
+
+    #[test]
+    fn code_is_accepted_one_step_around() {
+        let step = step_of(1_111_111_109);
+
+        assert_eq!(
+            matching_step(SECRET, "081804", step, None).unwrap(),
+            Some(step)
+        );
+        assert_eq!(
+            matching_step(SECRET, "081804", step - 1, None).unwrap(),
+            Some(step)
+        );
+        assert_eq!(
+            matching_step(SECRET, "081804", step + 1, None).unwrap(),
+            Some(step)
+        );
+        assert_eq!(
+            matching_step(SECRET, "081804", step - 2, None).unwrap(),
+            None
+        );
+        assert_eq!(
+            matching_step(SECRET, "081804", step + 2, None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn malformed_code_is_rejected() {
+        let step = step_of(59);
+
+        assert_eq!(
+            matching_step(SECRET, " 287082 ", step, None).unwrap(),
+            Some(step)
+        );
+        assert_eq!(matching_step(SECRET, "28708", step, None).unwrap(), None);
+        assert_eq!(matching_step(SECRET, "+87082", step, None).unwrap(), None);
+    }
+
+    #[test]
+    fn used_step_is_rejected() {
+        let step = step_of(1_111_111_109);
+
+        assert_eq!(
+            matching_step(SECRET, "081804", step, Some(step - 1)).unwrap(),
+            Some(step)
+        );
+        assert_eq!(
+            matching_step(SECRET, "081804", step, Some(step)).unwrap(),
+            None
+        );
+        assert_eq!(
+            matching_step(SECRET, "081804", step + 1, Some(step + 1)).unwrap(),
+            None
+        );
+    }
+}
@@ -21,6 +21,18 @@ macro_rules! uuid_wrapper {
             }
         }
 
+        impl From<uuid::Uuid> for $type {
+            fn from(id: uuid::Uuid) -> Self {
+                Self(id)
+            }
+        }
+
+        impl From<$type> for uuid::Uuid {
+            fn from(id: $type) -> Self {
+                id.0
+            }
+        }
+
         impl actix_web::FromRequest for $type {
             type Error = crate::error::APIError;
             type Future = futures::future::Ready<Result<Self, Self::Error>>;